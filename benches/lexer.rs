@@ -0,0 +1,51 @@
+// benches/lexer.rs
+//
+// Lexes generated sources of doubling size and reports the throughput of
+// each run. Run with `cargo bench --bench lexer`; a linear lexer keeps the
+// ns/byte column roughly constant as the input grows.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use nova_compiler::lexer::Lexer;
+
+const CHUNK: &str = r#"// Function definition
+fn add(a, b) {
+    return a + b;
+}
+let x = 42;
+let y = x + 5 - 3 * 7 / 2;
+let greeting = "héllo wörld";
+/* block
+   comment */
+"#;
+
+fn generate(size: usize) -> String {
+    let mut source = String::with_capacity(size + CHUNK.len());
+    while source.len() < size {
+        source.push_str(CHUNK);
+    }
+    source
+}
+
+fn lex_all(source: &str) -> usize {
+    Lexer::new(source).count() - 1
+}
+
+fn main() {
+    println!("{:>10} {:>12} {:>12} {:>10}", "bytes", "tokens", "time (ms)", "ns/byte");
+    for megabytes in [1, 2, 4, 8, 16] {
+        let source = generate(megabytes * 1024 * 1024);
+        let bytes = source.len();
+        let start = Instant::now();
+        let tokens = black_box(lex_all(black_box(&source)));
+        let elapsed = start.elapsed();
+        println!(
+            "{:>10} {:>12} {:>12.2} {:>10.2}",
+            bytes,
+            tokens,
+            elapsed.as_secs_f64() * 1e3,
+            elapsed.as_nanos() as f64 / bytes as f64
+        );
+    }
+}
@@ -1,34 +1,143 @@
 // src/ast.rs
 
-#[derive(PartialEq, Debug)]
+use std::fmt;
+
+use crate::token::Span;
+
+#[derive(PartialEq, Debug, Default)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum Statement {
-    Let(String, Expression),
+    // Declarations carry the text of any doc comments written before them.
+    Let(String, Expression, Option<String>),
     Return(Expression),
     Expression(Expression),
-    If(Box<Expression>, Box<Vec<Statement>>, Option<Box<Vec<Statement>>>),
-    Function(String, Vec<String>, Vec<Statement>),
+    Function(String, Vec<String>, Vec<Statement>, Option<String>),
+    While(Expression, Vec<Statement>),
+    Break,
+    Continue,
 }
 
-#[derive(PartialEq, Debug)]
+// The nodes that can fail at run time carry the span to report: the name
+// for identifiers, the operator for prefix and infix expressions, and the
+// start of the callee or assignment target for calls and assignments.
+#[derive(PartialEq, Debug, Clone)]
 pub enum Expression {
-    Identifier(String),
+    Identifier(String, Span),
     IntegerLiteral(i64),
-    Prefix(String, Box<Expression>),
-    Infix(String, Box<Expression>, Box<Expression>),
+    FloatLiteral(f64),
+    StringLiteral(String),
+    Prefix(String, Box<Expression>, Span),
+    Infix(String, Box<Expression>, Box<Expression>, Span),
     Boolean(bool),
-    If(Box<Expression>, Box<Statement>, Option<Box<Statement>>),
+    // `if` is always an expression; an `if` written as a statement is wrapped
+    // in `Statement::Expression`. An `else if` chain nests another `If` as the
+    // sole statement of the alternative block.
+    If(Box<Expression>, Vec<Statement>, Option<Vec<Statement>>),
     Function(Vec<String>, Vec<Statement>),
-    Call(Box<Expression>, Vec<Expression>),
+    Call(Box<Expression>, Vec<Expression>, Span),
+    // Operator (`=`, `+=`, ...), target name, value.
+    Assign(String, String, Box<Expression>, Span),
 }
 
-#[derive(PartialEq, PartialOrd)] 
-enum Precedence {
-    Lowest,
-    Equals,      
-    LessGreater, 
-    Sum,         
-    Product,      
-    Prefix,      
-    Call,        
+fn join<T: fmt::Display>(items: &[T], separator: &str) -> String {
+    items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(separator)
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn write_doc(f: &mut fmt::Formatter, doc: &Option<String>) -> fmt::Result {
+    if let Some(doc) = doc {
+        for line in doc.lines() {
+            writeln!(f, "/// {}", line)?;
+        }
+    }
+    Ok(())
 }
 
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for statement in &self.statements {
+            writeln!(f, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Let(name, value, doc) => {
+                write_doc(f, doc)?;
+                write!(f, "let {} = {};", name, value)
+            },
+            Statement::Return(value) => write!(f, "return {};", value),
+            Statement::Expression(expression) => write!(f, "{};", expression),
+            Statement::Function(name, parameters, body, doc) => {
+                write_doc(f, doc)?;
+                write!(f, "fn {}({}) {{ {} }}", name, parameters.join(", "), join(body, " "))
+            },
+            Statement::While(condition, body) => write!(f, "while ({}) {{ {} }}", condition, join(body, " ")),
+            Statement::Break => write!(f, "break;"),
+            Statement::Continue => write!(f, "continue;"),
+        }
+    }
+}
+
+// Operators are fully parenthesized so the shape of the tree is visible.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Identifier(name, _) => write!(f, "{}", name),
+            Expression::IntegerLiteral(value) => write!(f, "{}", value),
+            Expression::FloatLiteral(value) => write!(f, "{:?}", value),
+            Expression::StringLiteral(value) => write!(f, "\"{}\"", escape(value)),
+            Expression::Prefix(operator, operand, _) => write!(f, "({}{})", operator, operand),
+            Expression::Infix(operator, left, right, _) => write!(f, "({} {} {})", left, operator, right),
+            Expression::Boolean(value) => write!(f, "{}", value),
+            Expression::If(condition, consequence, alternative) => {
+                write!(f, "if ({}) {{ {} }}", condition, join(consequence, " "))?;
+                if let Some(alternative) = alternative {
+                    write!(f, " else {{ {} }}", join(alternative, " "))?;
+                }
+                Ok(())
+            },
+            Expression::Function(parameters, body) => {
+                write!(f, "fn({}) {{ {} }}", parameters.join(", "), join(body, " "))
+            },
+            Expression::Call(function, arguments, _) => write!(f, "{}({})", function, join(arguments, ", ")),
+            Expression::Assign(operator, name, value, _) => write!(f, "({} {} {})", name, operator, value),
+        }
+    }
+}
+
+#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+pub enum Precedence {
+    Lowest,
+    Assign,
+    LogicalOr,
+    LogicalAnd,
+    Equals,
+    LessGreater,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+}
@@ -0,0 +1,713 @@
+// src/compiler.rs
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{Expression, Program, Statement};
+use crate::diagnostic::Diagnostic;
+use crate::token::Span;
+use crate::value::{loop_control_outside_loop, Builtin, Value};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+impl BinaryOp {
+    fn from_operator(operator: &str) -> BinaryOp {
+        match operator {
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Subtract,
+            "*" => BinaryOp::Multiply,
+            "/" => BinaryOp::Divide,
+            "==" => BinaryOp::Equal,
+            "!=" => BinaryOp::NotEqual,
+            "<" => BinaryOp::Less,
+            ">" => BinaryOp::Greater,
+            "<=" => BinaryOp::LessEqual,
+            ">=" => BinaryOp::GreaterEqual,
+            _ => unreachable!("the parser only produces known operators, got '{}'", operator),
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::Greater => ">",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::GreaterEqual => ">=",
+        }
+    }
+}
+
+// Operands index the chunk's constants or functions, the global slots, the
+// stack slots of the current frame (slot 0 holds the function being run) or
+// its closure's upvalues; jump operands are instruction indexes. Every
+// instruction fits in eight bytes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Instruction {
+    Constant(u32),
+    Null,
+    True,
+    False,
+    Pop,
+    // Exchanges the top two values.
+    Swap,
+    GetGlobal(u32),
+    // Reads the variable a compound assignment updates, which only differs
+    // from `GetGlobal` in how an undefined variable is reported.
+    GetGlobalForUpdate(u32),
+    DefineGlobal(u32),
+    SetGlobal(u32),
+    GetLocal(u32),
+    SetLocal(u32),
+    GetUpvalue(u32),
+    SetUpvalue(u32),
+    // Sets aside the slot of a local whose declaration hasn't run yet.
+    Uninitialized,
+    // Reads or writes the first bound variable of one of the chunk's
+    // lookups, for a name that may not be bound where it is first looked.
+    GetVariable(u32),
+    GetVariableForUpdate(u32),
+    SetVariable(u32),
+    Negate,
+    Not,
+    Binary(BinaryOp),
+    Jump(u32),
+    // Pops the condition and jumps if it is false.
+    JumpIfFalse(u32),
+    // Calls the function below that many arguments.
+    Call(u32),
+    Closure(u32),
+    // Drops that many values from under the top of the stack: the locals of
+    // a block whose value is on top.
+    EndScope(u32),
+    // Cuts the stack back to that many slots, for `break` and `continue`
+    // leaving blocks and half-evaluated expressions behind.
+    Truncate(u32),
+    Return,
+}
+
+// A closure captures either a stack slot of the function it is created in
+// or one of that function's own upvalues.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UpvalueSource {
+    Local(u32),
+    Upvalue(u32),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Access {
+    Local(u32),
+    Upvalue(u32),
+    Global(u32),
+}
+
+// The interpreter looks a name up in each enclosing scope in turn when the
+// code runs, taking the first that has bound it by then. A lookup lists the
+// variables of that name in those scopes, innermost first, to be tried the
+// same way.
+#[derive(Debug, PartialEq)]
+pub struct Lookup {
+    pub name: String,
+    pub candidates: Vec<Access>,
+}
+
+// `spans` runs parallel to `code`, giving the source location to report if
+// that instruction fails.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    pub functions: Vec<Rc<CompiledFunction>>,
+    pub lookups: Vec<Lookup>,
+}
+
+#[derive(Debug, Default)]
+pub struct CompiledFunction {
+    pub name: Option<String>,
+    pub arity: usize,
+    pub chunk: Chunk,
+    pub upvalues: Vec<UpvalueSource>,
+}
+
+// The compiled top-level code, with the names of every global slot known so
+// far for error messages.
+#[derive(Debug)]
+pub struct Script {
+    pub function: Rc<CompiledFunction>,
+    pub globals: Vec<String>,
+}
+
+// `initialized` is set once the declaration has been compiled, from where
+// on the local is certain to be bound whenever it is in scope.
+struct Local {
+    name: String,
+    slot: u32,
+    depth: usize,
+    initialized: bool,
+}
+
+struct Loop {
+    start: u32,
+    height: u32,
+    breaks: Vec<usize>,
+}
+
+// The function being compiled. `height` tracks how many values the frame
+// will have on the stack at the current point, so that locals know their
+// slot even when declared in a block nested inside an expression.
+struct FunctionState {
+    function: CompiledFunction,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    height: u32,
+    loops: Vec<Loop>,
+}
+
+enum Variable {
+    Local(u32),
+    Upvalue(u32),
+    Global(u32),
+    Lookup(u32),
+}
+
+// Lowers the AST to bytecode. Top-level `let`s and functions become global
+// slots, which keep their numbering across calls to `compile`, so a VM fed
+// one program after another (as the REPL does) keeps its globals. Every
+// other binding lives in a stack slot, set aside when its scope is entered;
+// closures reach the ones of enclosing functions through upvalues.
+pub struct Compiler {
+    globals: HashMap<String, u32>,
+    global_names: Vec<String>,
+    functions: Vec<FunctionState>,
+    // The first statement found that can't be compiled.
+    error: Option<Box<Diagnostic>>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    // Builtins take the first global slots, in the order of `Builtin::ALL`.
+    pub fn new() -> Self {
+        let mut compiler = Compiler { globals: HashMap::new(), global_names: Vec::new(), functions: Vec::new(), error: None };
+        for builtin in Builtin::ALL {
+            compiler.global(builtin.name());
+        }
+        compiler
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<Script, Box<Diagnostic>> {
+        self.functions.push(FunctionState {
+            function: CompiledFunction::default(),
+            locals: Vec::new(),
+            scope_depth: 0,
+            height: 1,
+            loops: Vec::new(),
+        });
+        self.block_value(&program.statements);
+        self.emit(Instruction::Return, Span::default());
+        let state = self.functions.pop().expect("script state");
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        Ok(Script { function: Rc::new(state.function), globals: self.global_names.clone() })
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("a function being compiled")
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        let state = self.state();
+        state.height = match instruction {
+            Instruction::Constant(_) | Instruction::Null | Instruction::True | Instruction::False
+            | Instruction::GetGlobal(_) | Instruction::GetGlobalForUpdate(_) | Instruction::GetLocal(_)
+            | Instruction::GetUpvalue(_) | Instruction::Closure(_) | Instruction::Uninitialized
+            | Instruction::GetVariable(_) | Instruction::GetVariableForUpdate(_) => state.height + 1,
+            Instruction::Pop | Instruction::DefineGlobal(_) | Instruction::Binary(_)
+            | Instruction::JumpIfFalse(_) | Instruction::Return => state.height - 1,
+            Instruction::Call(arguments) | Instruction::EndScope(arguments) => state.height - arguments,
+            Instruction::Swap | Instruction::SetGlobal(_) | Instruction::SetLocal(_) | Instruction::SetUpvalue(_)
+            | Instruction::SetVariable(_) | Instruction::Negate | Instruction::Not => state.height,
+            // The code after these only runs if something jumps to it, and
+            // every jump leaves the stack as high as the code before them.
+            Instruction::Jump(_) | Instruction::Truncate(_) => state.height,
+        };
+        state.function.chunk.code.push(instruction);
+        state.function.chunk.spans.push(span);
+        state.function.chunk.code.len() - 1
+    }
+
+    fn here(&mut self) -> u32 {
+        self.state().function.chunk.code.len() as u32
+    }
+
+    // Points the jump at `index` to the next instruction to be emitted.
+    fn patch(&mut self, index: usize) {
+        let target = self.here();
+        match &mut self.state().function.chunk.code[index] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) => *to = target,
+            other => unreachable!("patching {:?}", other),
+        }
+    }
+
+    fn constant(&mut self, value: Value) {
+        let constants = &mut self.state().function.chunk.constants;
+        constants.push(value);
+        let index = constants.len() as u32 - 1;
+        self.emit(Instruction::Constant(index), Span::default());
+    }
+
+    fn global(&mut self, name: &str) -> u32 {
+        if let Some(&slot) = self.globals.get(name) {
+            return slot;
+        }
+        let slot = self.global_names.len() as u32;
+        self.globals.insert(name.to_string(), slot);
+        self.global_names.push(name.to_string());
+        slot
+    }
+
+    fn at_global_scope(&self) -> bool {
+        self.functions.len() == 1 && self.functions[0].scope_depth == 0
+    }
+
+    // Compiles a list of statements so that it leaves exactly one value on
+    // the stack: that of its last statement if that is an expression, and
+    // null otherwise.
+    fn block_value(&mut self, statements: &[Statement]) {
+        match statements.split_last() {
+            Some((last, rest)) => {
+                for statement in rest {
+                    self.statement(statement);
+                }
+                match last {
+                    Statement::Expression(expression) => self.expression(expression),
+                    statement => {
+                        self.statement(statement);
+                        self.emit(Instruction::Null, Span::default());
+                    },
+                }
+            },
+            None => {
+                self.emit(Instruction::Null, Span::default());
+            },
+        }
+    }
+
+    fn scoped_block_value(&mut self, statements: &[Statement]) {
+        let start = self.state().height;
+        self.state().scope_depth += 1;
+        self.declare_locals(statements);
+        self.block_value(statements);
+        let state = self.state();
+        let depth = state.scope_depth;
+        state.locals.retain(|local| local.depth < depth);
+        state.scope_depth -= 1;
+        let locals = state.height - 1 - start;
+        if locals > 0 {
+            self.emit(Instruction::EndScope(locals), Span::default());
+        }
+    }
+
+    // Sets aside a slot for every name the block declares, so a closure
+    // created before a declaration can still see the variable once it runs.
+    fn declare_locals(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            let (Statement::Let(name, ..) | Statement::Function(name, ..)) = statement else {
+                continue;
+            };
+            let state = self.state();
+            let depth = state.scope_depth;
+            if !state.locals.iter().any(|local| local.depth == depth && local.name == *name) {
+                let slot = state.height;
+                state.locals.push(Local { name: name.clone(), slot, depth, initialized: false });
+                self.emit(Instruction::Uninitialized, Span::default());
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(name, value, _) => {
+                self.expression(value);
+                self.declaration(name);
+            },
+            Statement::Function(name, parameters, body, _) => {
+                self.function(Some(name), parameters, body);
+                self.declaration(name);
+            },
+            Statement::Return(value) => {
+                self.expression(value);
+                self.emit(Instruction::Return, Span::default());
+            },
+            Statement::Expression(expression) => {
+                self.expression(expression);
+                self.emit(Instruction::Pop, Span::default());
+            },
+            Statement::While(condition, body) => {
+                let start = self.here();
+                self.expression(condition);
+                let exit = self.emit(Instruction::JumpIfFalse(0), Span::default());
+                let height = self.state().height;
+                self.state().loops.push(Loop { start, height, breaks: Vec::new() });
+                self.scoped_block_value(body);
+                self.emit(Instruction::Pop, Span::default());
+                self.emit(Instruction::Jump(start), Span::default());
+                self.patch(exit);
+                let finished = self.state().loops.pop().expect("loop being compiled");
+                for jump in finished.breaks {
+                    self.patch(jump);
+                }
+            },
+            Statement::Break | Statement::Continue => {
+                let state = self.state();
+                let Some((start, height)) = state.loops.last().map(|l| (l.start, l.height)) else {
+                    self.error.get_or_insert_with(|| loop_control_outside_loop(statement));
+                    return;
+                };
+                if state.height > height {
+                    self.emit(Instruction::Truncate(height), Span::default());
+                }
+                if matches!(statement, Statement::Break) {
+                    let jump = self.emit(Instruction::Jump(0), Span::default());
+                    self.state().loops.last_mut().expect("loop being compiled").breaks.push(jump);
+                } else {
+                    self.emit(Instruction::Jump(start), Span::default());
+                }
+            },
+        }
+    }
+
+    // Binds the value on top of the stack to a declared name. Redeclaring a
+    // name in the same scope replaces its value, as it does in the
+    // interpreter's environment.
+    fn declaration(&mut self, name: &str) {
+        if self.at_global_scope() {
+            let slot = self.global(name);
+            self.emit(Instruction::DefineGlobal(slot), Span::default());
+            return;
+        }
+        let state = self.state();
+        let depth = state.scope_depth;
+        let local = state.locals.iter_mut().rev().find(|local| local.depth == depth && local.name == name)
+            .expect("locals are declared when their scope starts");
+        local.initialized = true;
+        let slot = local.slot;
+        self.emit(Instruction::SetLocal(slot), Span::default());
+        self.emit(Instruction::Pop, Span::default());
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(name, span) => {
+                let instruction = match self.resolve(name) {
+                    Variable::Local(slot) => Instruction::GetLocal(slot),
+                    Variable::Upvalue(index) => Instruction::GetUpvalue(index),
+                    Variable::Global(slot) => Instruction::GetGlobal(slot),
+                    Variable::Lookup(index) => Instruction::GetVariable(index),
+                };
+                self.emit(instruction, *span);
+            },
+            Expression::IntegerLiteral(value) => self.constant(Value::Integer(*value)),
+            Expression::FloatLiteral(value) => self.constant(Value::Float(*value)),
+            Expression::StringLiteral(value) => self.constant(Value::String(Rc::from(value.as_str()))),
+            Expression::Boolean(true) => {
+                self.emit(Instruction::True, Span::default());
+            },
+            Expression::Boolean(false) => {
+                self.emit(Instruction::False, Span::default());
+            },
+            Expression::Prefix(operator, operand, span) => {
+                self.expression(operand);
+                let instruction = if operator == "-" { Instruction::Negate } else { Instruction::Not };
+                self.emit(instruction, *span);
+            },
+            // `a && b` is `if (a) { !!b } else { false }`, and `a || b` is
+            // `if (a) { true } else { !!b }`.
+            Expression::Infix(operator, left, right, _) if operator == "&&" || operator == "||" => {
+                self.expression(left);
+                let skip = self.emit(Instruction::JumpIfFalse(0), Span::default());
+                if operator == "&&" {
+                    self.truthiness(right);
+                } else {
+                    self.emit(Instruction::True, Span::default());
+                }
+                let end = self.emit(Instruction::Jump(0), Span::default());
+                self.state().height -= 1;
+                self.patch(skip);
+                if operator == "&&" {
+                    self.emit(Instruction::False, Span::default());
+                } else {
+                    self.truthiness(right);
+                }
+                self.patch(end);
+            },
+            Expression::Infix(operator, left, right, span) => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Instruction::Binary(BinaryOp::from_operator(operator)), *span);
+            },
+            Expression::If(condition, consequence, alternative) => {
+                self.expression(condition);
+                let otherwise = self.emit(Instruction::JumpIfFalse(0), Span::default());
+                self.scoped_block_value(consequence);
+                let end = self.emit(Instruction::Jump(0), Span::default());
+                self.state().height -= 1;
+                self.patch(otherwise);
+                match alternative {
+                    Some(alternative) => self.scoped_block_value(alternative),
+                    None => {
+                        self.emit(Instruction::Null, Span::default());
+                    },
+                }
+                self.patch(end);
+            },
+            Expression::Function(parameters, body) => self.function(None, parameters, body),
+            Expression::Call(function, arguments, span) => {
+                self.expression(function);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.emit(Instruction::Call(arguments.len() as u32), *span);
+            },
+            // The value is computed before the variable is read, as in the
+            // interpreter, so the two are swapped back for the operator.
+            Expression::Assign(operator, name, value, span) => {
+                self.expression(value);
+                let variable = self.resolve(name);
+                if let Some(operator) = operator.strip_suffix('=').filter(|operator| !operator.is_empty()) {
+                    let read = match variable {
+                        Variable::Local(slot) => Instruction::GetLocal(slot),
+                        Variable::Upvalue(index) => Instruction::GetUpvalue(index),
+                        Variable::Global(slot) => Instruction::GetGlobalForUpdate(slot),
+                        Variable::Lookup(index) => Instruction::GetVariableForUpdate(index),
+                    };
+                    self.emit(read, *span);
+                    self.emit(Instruction::Swap, Span::default());
+                    self.emit(Instruction::Binary(BinaryOp::from_operator(operator)), *span);
+                }
+                let write = match variable {
+                    Variable::Local(slot) => Instruction::SetLocal(slot),
+                    Variable::Upvalue(index) => Instruction::SetUpvalue(index),
+                    Variable::Global(slot) => Instruction::SetGlobal(slot),
+                    Variable::Lookup(index) => Instruction::SetVariable(index),
+                };
+                self.emit(write, *span);
+            },
+        }
+    }
+
+    fn truthiness(&mut self, expression: &Expression) {
+        self.expression(expression);
+        self.emit(Instruction::Not, Span::default());
+        self.emit(Instruction::Not, Span::default());
+    }
+
+    fn function(&mut self, name: Option<&str>, parameters: &[String], body: &[Statement]) {
+        let locals = parameters.iter().enumerate().map(|(index, parameter)| Local {
+            name: parameter.clone(),
+            slot: index as u32 + 1,
+            depth: 1,
+            initialized: true,
+        }).collect();
+        self.functions.push(FunctionState {
+            function: CompiledFunction {
+                name: name.map(str::to_string),
+                arity: parameters.len(),
+                ..CompiledFunction::default()
+            },
+            locals,
+            scope_depth: 1,
+            height: parameters.len() as u32 + 1,
+            loops: Vec::new(),
+        });
+        self.declare_locals(body);
+        self.block_value(body);
+        self.emit(Instruction::Return, Span::default());
+        let state = self.functions.pop().expect("function state");
+        let functions = &mut self.state().function.chunk.functions;
+        functions.push(Rc::new(state.function));
+        let index = functions.len() as u32 - 1;
+        self.emit(Instruction::Closure(index), Span::default());
+    }
+
+    // A name is read or written directly when the innermost variable of that
+    // name is certain to be bound, and through a lookup otherwise.
+    fn resolve(&mut self, name: &str) -> Variable {
+        let level = self.functions.len() - 1;
+        let mut candidates = Vec::new();
+        for function in (0..=level).rev() {
+            let locals: Vec<(u32, bool)> = self.functions[function].locals.iter().rev()
+                .filter(|local| local.name == name)
+                .map(|local| (local.slot, local.initialized))
+                .collect();
+            for (slot, initialized) in locals {
+                let access = if function == level {
+                    Access::Local(slot)
+                } else {
+                    Access::Upvalue(self.capture(level, function, slot))
+                };
+                candidates.push(access);
+                if initialized {
+                    return self.lookup(name, candidates);
+                }
+            }
+        }
+        candidates.push(Access::Global(self.global(name)));
+        self.lookup(name, candidates)
+    }
+
+    fn lookup(&mut self, name: &str, candidates: Vec<Access>) -> Variable {
+        match candidates[..] {
+            [Access::Local(slot)] => Variable::Local(slot),
+            [Access::Upvalue(index)] => Variable::Upvalue(index),
+            [Access::Global(slot)] => Variable::Global(slot),
+            _ => {
+                let lookups = &mut self.state().function.chunk.lookups;
+                lookups.push(Lookup { name: name.to_string(), candidates });
+                Variable::Lookup(lookups.len() as u32 - 1)
+            },
+        }
+    }
+
+    // The upvalue through which the function at `level` reaches `slot` of
+    // the enclosing function at `target`.
+    fn capture(&mut self, level: usize, target: usize, slot: u32) -> u32 {
+        let source = if level == target + 1 {
+            UpvalueSource::Local(slot)
+        } else {
+            UpvalueSource::Upvalue(self.capture(level - 1, target, slot))
+        };
+        let upvalues = &mut self.functions[level].function.upvalues;
+        let index = upvalues.iter().position(|&upvalue| upvalue == source).unwrap_or_else(|| {
+            upvalues.push(source);
+            upvalues.len() - 1
+        });
+        index as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_ok;
+
+    fn compile(input: &str) -> Script {
+        Compiler::new().compile(&parse_ok(input)).unwrap()
+    }
+
+    #[test]
+    fn test_instructions_are_compact() {
+        assert!(std::mem::size_of::<Instruction>() <= 8);
+    }
+
+    #[test]
+    fn test_globals_and_constants() {
+        let script = compile("let x = 1 + 2; print(x);");
+        assert_eq!(script.function.chunk.code, [
+            Instruction::Constant(0),
+            Instruction::Constant(1),
+            Instruction::Binary(BinaryOp::Add),
+            Instruction::DefineGlobal(1),
+            Instruction::GetGlobal(0),
+            Instruction::GetGlobal(1),
+            Instruction::Call(1),
+            Instruction::Return,
+        ]);
+        assert_eq!(script.function.chunk.constants, [Value::Integer(1), Value::Integer(2)]);
+        assert_eq!(script.globals, ["print", "x"]);
+    }
+
+    #[test]
+    fn test_block_locals_and_scopes() {
+        let script = compile("if (true) { let a = 1; let b = a; b }");
+        assert_eq!(script.function.chunk.code, [
+            Instruction::True,
+            Instruction::JumpIfFalse(13),
+            Instruction::Uninitialized,
+            Instruction::Uninitialized,
+            Instruction::Constant(0),
+            Instruction::SetLocal(1),
+            Instruction::Pop,
+            Instruction::GetLocal(1),
+            Instruction::SetLocal(2),
+            Instruction::Pop,
+            Instruction::GetLocal(2),
+            Instruction::EndScope(2),
+            Instruction::Jump(14),
+            Instruction::Null,
+            Instruction::Return,
+        ]);
+    }
+
+    #[test]
+    fn test_closures_capture_enclosing_locals() {
+        let script = compile("fn outer(a) { let b = 2; return fn() { fn() { a + b } }; }");
+        let outer = &script.function.chunk.functions[0];
+        assert_eq!(outer.name.as_deref(), Some("outer"));
+        assert_eq!(outer.arity, 1);
+        let middle = &outer.chunk.functions[0];
+        assert_eq!(middle.upvalues, [UpvalueSource::Local(1), UpvalueSource::Local(2)]);
+        let inner = &middle.chunk.functions[0];
+        assert_eq!(inner.upvalues, [UpvalueSource::Upvalue(0), UpvalueSource::Upvalue(1)]);
+    }
+
+    #[test]
+    fn test_names_bound_later_are_looked_up() {
+        let script = compile("fn f() { let g = fn() { x }; let x = 5; g() }");
+        let f = &script.function.chunk.functions[0];
+        let g = &f.chunk.functions[0];
+        assert_eq!(g.upvalues, [UpvalueSource::Local(2)]);
+        assert_eq!(g.chunk.code[0], Instruction::GetVariable(0));
+        assert_eq!(g.chunk.lookups, [Lookup {
+            name: "x".to_string(),
+            candidates: vec![Access::Upvalue(0), Access::Global(1)],
+        }]);
+        // Once `x` is declared, it is certain to be bound.
+        assert!(f.chunk.code.contains(&Instruction::GetLocal(1)), "{:?}", f.chunk.code);
+    }
+
+    #[test]
+    fn test_locals_inside_expressions_get_the_right_slot() {
+        let script = compile("fn f(p) { return 10 + if (p) { let a = 1; a } else { 2 }; }");
+        let code = &script.function.chunk.functions[0].chunk.code;
+        // Slot 1 is `p` and slot 2 the pending `10`, so `a` is in slot 3.
+        assert!(code.contains(&Instruction::GetLocal(3)), "{:?}", code);
+    }
+
+    #[test]
+    fn test_break_truncates_to_loop_height() {
+        let script = compile("fn f() { let i = 0; while (true) { let j = 1; 5 + if (j) { break; } else { 1 }; } }");
+        let code = &script.function.chunk.functions[0].chunk.code;
+        assert!(code.contains(&Instruction::Truncate(2)), "{:?}", code);
+    }
+
+    #[test]
+    fn test_loop_control_outside_loops_is_an_error() {
+        let program = Program { statements: vec![Statement::Expression(Expression::IntegerLiteral(1)), Statement::Break] };
+        let error = Compiler::new().compile(&program).unwrap_err();
+        assert_eq!(error.message, "'break' outside of a loop");
+    }
+}
@@ -0,0 +1,247 @@
+// src/cst.rs
+
+use std::fmt;
+
+use crate::lexer::Lexer;
+use crate::token::{Token, TokenType};
+
+// A concrete syntax tree groups every token of a file, trivia included, into
+// statements, `{ }` blocks and `( )` groups. Nothing is dropped or
+// normalized, so writing the tree back out reproduces the source exactly.
+// Unlike the AST it is built for any input, however malformed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SyntaxKind {
+    Root,
+    Statement,
+    Block,
+    Group,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> Self {
+        SyntaxNode { kind, children: Vec::new() }
+    }
+
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.tokens() {
+            for trivia in &token.leading_trivia {
+                f.write_str(&trivia.text)?;
+            }
+            f.write_str(&token.literal)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(input: &str) -> SyntaxNode {
+    let tokens: Vec<Token> = Lexer::new_lossless(input).collect();
+    let mut builder = Builder { tokens: tokens.into_iter().peekable() };
+    let mut root = builder.statements(SyntaxKind::Root);
+    root.children.extend(builder.tokens.map(SyntaxElement::Token));
+    root
+}
+
+struct Builder {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Builder {
+    fn peek_is(&mut self, token_type: TokenType) -> bool {
+        self.tokens.peek().is_some_and(|token| token.token_type == token_type)
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek_is(TokenType::EOF) || self.tokens.peek().is_none()
+    }
+
+    // Collects statements up to a `}` (left for the caller) or EOF.
+    fn statements(&mut self, kind: SyntaxKind) -> SyntaxNode {
+        let mut node = SyntaxNode::new(kind);
+        while !self.at_end() {
+            if kind == SyntaxKind::Block && self.peek_is(TokenType::RBrace) {
+                break;
+            }
+            node.children.push(SyntaxElement::Node(self.statement()));
+        }
+        node
+    }
+
+    // A statement runs to its `;`, or to the block that closes an `if`,
+    // `while` or function declaration unless an `else` follows. A `}` ends
+    // the statement without being part of it, except for a stray one
+    // outside any block, which becomes a statement of its own.
+    fn statement(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::Statement);
+        let mut ends_with_block = false;
+        while let Some(token) = self.tokens.peek() {
+            let token_type = token.token_type.clone();
+            let at_start = node.children.iter().all(|child| matches!(child,
+                SyntaxElement::Token(Token { token_type: TokenType::DocComment(_), .. })));
+            match token_type {
+                TokenType::EOF => break,
+                TokenType::RBrace if !at_start => break,
+                TokenType::LBrace => {
+                    node.children.push(SyntaxElement::Node(self.block()));
+                    if ends_with_block && !self.peek_is(TokenType::Else) {
+                        break;
+                    }
+                    continue;
+                },
+                TokenType::LParen => {
+                    node.children.push(SyntaxElement::Node(self.group()));
+                    continue;
+                },
+                TokenType::If | TokenType::While | TokenType::Fn | TokenType::Function if at_start => {
+                    ends_with_block = true;
+                },
+                _ => {},
+            }
+            let token = self.tokens.next().expect("peeked token");
+            node.children.push(SyntaxElement::Token(token));
+            if matches!(token_type, TokenType::Semicolon | TokenType::RBrace) {
+                break;
+            }
+        }
+        node
+    }
+
+    fn block(&mut self) -> SyntaxNode {
+        let open = self.tokens.next().expect("peeked '{'");
+        let mut node = self.statements(SyntaxKind::Block);
+        node.children.insert(0, SyntaxElement::Token(open));
+        if self.peek_is(TokenType::RBrace) {
+            node.children.push(SyntaxElement::Token(self.tokens.next().expect("peeked '}'")));
+        }
+        node
+    }
+
+    // Groups hold loose tokens, except that a `{` inside them (the body of a
+    // function literal passed as an argument) still opens a block.
+    fn group(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::Group);
+        node.children.push(SyntaxElement::Token(self.tokens.next().expect("peeked '('")));
+        while let Some(token) = self.tokens.peek() {
+            match token.token_type {
+                TokenType::EOF | TokenType::RBrace | TokenType::Semicolon => break,
+                TokenType::LBrace => node.children.push(SyntaxElement::Node(self.block())),
+                TokenType::LParen => node.children.push(SyntaxElement::Node(self.group())),
+                TokenType::RParen => {
+                    node.children.push(SyntaxElement::Token(self.tokens.next().expect("peeked ')'")));
+                    break;
+                },
+                _ => node.children.push(SyntaxElement::Token(self.tokens.next().expect("peeked token"))),
+            }
+        }
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(input: &str) {
+        let tree = parse(input);
+        assert_eq!(tree.to_string(), input);
+    }
+
+    fn shape(node: &SyntaxNode) -> String {
+        let children: Vec<String> = node.children.iter().map(|child| match child {
+            SyntaxElement::Node(node) => shape(node),
+            SyntaxElement::Token(token) if token.token_type == TokenType::EOF => "EOF".to_string(),
+            SyntaxElement::Token(token) => token.literal.clone(),
+        }).collect();
+        format!("{:?}[{}]", node.kind, children.join(" "))
+    }
+
+    #[test]
+    fn test_round_trip_example_program() {
+        assert_round_trip(include_str!("../example.nova"));
+    }
+
+    #[test]
+    fn test_round_trip_preserves_everything() {
+        let inputs = [
+            "",
+            "   \n\t ",
+            "let x=1;// trailing comment",
+            "let  x =\r\n  1 ;\r\n",
+            "/* a /* nested */ comment */ let y = \"s\\n\\\"q\\\"\";",
+            "/// doc\n/** block doc */\nfn f( a ,b ){return a+b;}",
+            "let café = \"naïve ☃\"; //// not doc",
+            "if (x) { y } else if (z) { w; } else { }",
+        ];
+        for input in inputs {
+            assert_round_trip(input);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_malformed_input() {
+        let inputs = [
+            "let = ;; } } ) (",
+            "fn f(a, { let",
+            "let s = \"unterminated",
+            "x /* unterminated /* nested */",
+            "@ # $ 0b102 99999999999999999999 \"bad \\q\" a\0b",
+            "((((",
+            "}}}{{{",
+        ];
+        for input in inputs {
+            assert_round_trip(input);
+        }
+    }
+
+    #[test]
+    fn test_tree_shape() {
+        let input = "/// doc\nlet x = f(1, (2));\nif (x) { y; } else { z }\nfn g() { while (a) { b; } }\nx;";
+        let tree = parse(input);
+        assert_eq!(
+            shape(&tree),
+            "Root[\
+                Statement[/// doc let x = f Group[( 1 , Group[( 2 )] )] ;] \
+                Statement[if Group[( x )] Block[{ Statement[y ;] }] else Block[{ Statement[z] }]] \
+                Statement[fn g Group[( )] Block[{ Statement[while Group[( a )] Block[{ Statement[b ;] }]] }]] \
+                Statement[x ;] \
+                EOF]"
+        );
+    }
+
+    #[test]
+    fn test_function_literal_statements_end_at_semicolon() {
+        let tree = parse("let f = fn(a) { a }; f(1);");
+        assert_eq!(
+            shape(&tree),
+            "Root[Statement[let f = fn Group[( a )] Block[{ Statement[a] }] ;] Statement[f Group[( 1 )] ;] EOF]"
+        );
+    }
+}
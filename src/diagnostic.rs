@@ -0,0 +1,200 @@
+// src/diagnostic.rs
+
+use std::fmt;
+
+use crate::token::{LexError, Span, Token, TokenType};
+
+// Every diagnostic has a stable code so tools can filter on it. Codes in the
+// E00xx range come from the lexer, E01xx from the parser and E02xx from
+// running a program.
+pub mod codes {
+    pub const ILLEGAL_CHARACTER: &str = "E0001";
+    pub const UNTERMINATED_STRING: &str = "E0002";
+    pub const INVALID_ESCAPE: &str = "E0003";
+    pub const INVALID_NUMBER: &str = "E0004";
+    pub const INTEGER_OVERFLOW: &str = "E0005";
+    pub const UNTERMINATED_COMMENT: &str = "E0006";
+    pub const IO_ERROR: &str = "E0007";
+    pub const FLOAT_OVERFLOW: &str = "E0008";
+
+    pub const EXPECTED_EXPRESSION: &str = "E0101";
+    pub const UNEXPECTED_TOKEN: &str = "E0102";
+    pub const MISSING_SEMICOLON: &str = "E0103";
+    pub const EXPECTED_IDENTIFIER: &str = "E0104";
+    pub const UNCLOSED_DELIMITER: &str = "E0105";
+    pub const INVALID_ASSIGNMENT_TARGET: &str = "E0106";
+    pub const LOOP_CONTROL_OUTSIDE_LOOP: &str = "E0107";
+    pub const NESTING_TOO_DEEP: &str = "E0108";
+
+    pub const UNDEFINED_VARIABLE: &str = "E0201";
+    pub const TYPE_MISMATCH: &str = "E0202";
+    pub const DIVISION_BY_ZERO: &str = "E0203";
+    pub const ARITHMETIC_OVERFLOW: &str = "E0204";
+    pub const NOT_CALLABLE: &str = "E0205";
+    pub const WRONG_ARGUMENT_COUNT: &str = "E0206";
+    pub const STACK_OVERFLOW: &str = "E0207";
+    pub const OUTPUT_ERROR: &str = "E0208";
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+// A secondary location that helps explain the primary one, such as the
+// opening brace of a block that is never closed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// A machine-applicable fix: replace the text at `span` (which may be empty,
+// for an insertion) with `replacement`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, span: Span, replacement: impl Into<String>, message: impl Into<String>) -> Self {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    // The lexical diagnostic carried by an `Error` or `Illegal` token.
+    pub fn from_token(token: &Token) -> Option<Diagnostic> {
+        let diagnostic = match &token.token_type {
+            TokenType::Illegal => Diagnostic::error(
+                codes::ILLEGAL_CHARACTER,
+                token.span,
+                format!("Unexpected character '{}'", token.literal),
+            ),
+            TokenType::Error(error) => {
+                let diagnostic = Diagnostic::error(error.code(), token.span, error.to_string());
+                match error {
+                    LexError::UnterminatedString => diagnostic.with_note("Strings must be closed with '\"' before the end of the file"),
+                    LexError::InvalidEscape(_) => diagnostic.with_note("Valid escapes are \\n, \\t, \\r, \\0, \\\\, \\\" and \\u{...}"),
+                    LexError::InvalidNumber(_) => diagnostic.with_note("Numbers look like 42, 1_000, 3.14, 1e-9, 0xFF, 0b1010 or 0o755"),
+                    LexError::IntegerOverflow(_) => diagnostic.with_note(format!(
+                        "Integers must be between {} and {}", i64::MIN, i64::MAX
+                    )),
+                    LexError::FloatOverflow(_) => diagnostic.with_note(format!(
+                        "Floats must be at most {:e} in magnitude", f64::MAX
+                    )),
+                    LexError::UnterminatedComment => diagnostic.with_note("Block comments nest; each '/*' needs its own '*/'"),
+                    LexError::Io(_) => diagnostic,
+                }
+            },
+            _ => return None,
+        };
+        Some(diagnostic)
+    }
+}
+
+impl LexError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::UnterminatedString => codes::UNTERMINATED_STRING,
+            LexError::InvalidEscape(_) => codes::INVALID_ESCAPE,
+            LexError::InvalidNumber(_) => codes::INVALID_NUMBER,
+            LexError::IntegerOverflow(_) => codes::INTEGER_OVERFLOW,
+            LexError::FloatOverflow(_) => codes::FLOAT_OVERFLOW,
+            LexError::UnterminatedComment => codes::UNTERMINATED_COMMENT,
+            LexError::Io(_) => codes::IO_ERROR,
+        }
+    }
+}
+
+// The one-line form, `3:14: error[E0103]: Expected semicolon ...`; see the
+// renderer for the full form with source snippets.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}[{}]: {}", self.span, self.severity, self.code, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_lexical_diagnostics() {
+        let input = "@ \"bad \\q\" 0b12 99999999999999999999 x /* open";
+        let diagnostics: Vec<Diagnostic> = Lexer::new(input).filter_map(|token| Diagnostic::from_token(&token)).collect();
+        let codes: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, [
+            codes::ILLEGAL_CHARACTER, codes::INVALID_ESCAPE, codes::INVALID_NUMBER,
+            codes::INTEGER_OVERFLOW, codes::UNTERMINATED_COMMENT,
+        ]);
+        assert_eq!(diagnostics[0].to_string(), "1:1: error[E0001]: Unexpected character '@'");
+        assert_eq!(diagnostics[3].notes, ["Integers must be between -9223372036854775808 and 9223372036854775807"]);
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn test_builder_methods() {
+        let span = Span { start: 4, end: 5, line: 1, column: 5 };
+        let open = Span { start: 0, end: 1, line: 1, column: 1 };
+        let diagnostic = Diagnostic::error(codes::UNCLOSED_DELIMITER, span, "Expected ')'")
+            .with_label(open, "unclosed '('")
+            .with_note("a note")
+            .with_suggestion(span, ")", "close the parenthesis");
+        assert_eq!(diagnostic.labels, [Label { span: open, message: "unclosed '('".to_string() }]);
+        assert_eq!(diagnostic.notes, ["a note"]);
+        assert_eq!(diagnostic.suggestions[0].replacement, ")");
+        assert_eq!(diagnostic.to_string(), "1:5: error[E0105]: Expected ')'");
+    }
+}
@@ -0,0 +1,469 @@
+// src/eval.rs
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::ast::{Expression, Program, Statement};
+use crate::diagnostic::Diagnostic;
+use crate::token::Span;
+use crate::value::{
+    binary, call_builtin, loop_control_outside_loop, not_callable, stack_overflow, unary, undefined_assignment, undefined_variable,
+    wrong_argument_count, Builtin, Value, MAX_CALL_DEPTH,
+};
+
+// Each level of a Nova call takes several Rust frames, several kilobytes
+// in a debug build, so a thread running a deep recursion needs a stack well
+// beyond the default: `STACK_SIZE` leaves room for the deepest allowed.
+pub const STACK_SIZE: usize = 256 << 20;
+
+// A function value closes over the environment it was defined in, so it can
+// still see (and update) the variables around its definition after that
+// scope has been left.
+pub struct Function {
+    pub name: Option<String>,
+    pub parameters: Vec<String>,
+    pub body: Vec<Statement>,
+    env: Env,
+}
+
+// The environment is left out: a recursive function's environment holds the
+// function itself.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("parameters", &self.parameters)
+            .finish_non_exhaustive()
+    }
+}
+
+type Env = Rc<RefCell<Environment>>;
+
+#[derive(Default)]
+struct Environment {
+    values: HashMap<String, Value>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    fn enclosed(outer: &Env) -> Env {
+        Rc::new(RefCell::new(Environment { values: HashMap::new(), outer: Some(Rc::clone(outer)) }))
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref()?.borrow().get(name),
+        }
+    }
+
+    // `let` always binds in the innermost scope, shadowing any outer binding.
+    fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    // Assignment updates the nearest existing binding.
+    fn assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
+
+// Everything that stops a statement list early. `Return` stops at the
+// call it is in, or ends the program at the top level; `Break` and
+// `Continue` stop at their loop, and are an error if they escape one. Errors
+// are boxed throughout to keep results small, since every level of a deep
+// recursion holds a few.
+enum Unwind {
+    Return(Value),
+    Break,
+    Continue,
+    Error(Box<Diagnostic>),
+}
+
+impl From<Box<Diagnostic>> for Unwind {
+    fn from(diagnostic: Box<Diagnostic>) -> Self {
+        Unwind::Error(diagnostic)
+    }
+}
+
+type Flow<T> = Result<T, Unwind>;
+
+pub struct Interpreter<W: Write = io::Stdout> {
+    globals: Env,
+    output: W,
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter::with_output(io::stdout())
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl<W: Write> Interpreter<W> {
+    pub fn with_output(output: W) -> Self {
+        let globals = Env::default();
+        for builtin in Builtin::ALL {
+            globals.borrow_mut().define(builtin.name(), Value::Builtin(builtin));
+        }
+        Interpreter { globals, output, depth: 0 }
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    // Runs the program's statements in the global scope, which outlives the
+    // call, and gives back the value of the last statement or of a top-level
+    // `return`.
+    pub fn run(&mut self, program: &Program) -> Result<Value, Box<Diagnostic>> {
+        let globals = Rc::clone(&self.globals);
+        match self.eval_statements(&program.statements, &globals) {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(diagnostic)) => Err(diagnostic),
+            Err(Unwind::Break) => Err(loop_control_outside_loop(&Statement::Break)),
+            Err(Unwind::Continue) => Err(loop_control_outside_loop(&Statement::Continue)),
+        }
+    }
+
+    fn eval_statements(&mut self, statements: &[Statement], env: &Env) -> Flow<Value> {
+        let mut value = Value::Null;
+        for statement in statements {
+            value = self.eval_statement(statement, env)?;
+        }
+        Ok(value)
+    }
+
+    fn eval_block(&mut self, statements: &[Statement], env: &Env) -> Flow<Value> {
+        self.eval_statements(statements, &Environment::enclosed(env))
+    }
+
+    fn eval_statement(&mut self, statement: &Statement, env: &Env) -> Flow<Value> {
+        match statement {
+            Statement::Let(name, value, _) => {
+                let value = self.eval_expression(value, env)?;
+                env.borrow_mut().define(name, value);
+                Ok(Value::Null)
+            },
+            Statement::Return(value) => Err(Unwind::Return(self.eval_expression(value, env)?)),
+            Statement::Expression(expression) => self.eval_expression(expression, env),
+            Statement::Function(name, parameters, body, _) => {
+                let function = Function {
+                    name: Some(name.clone()),
+                    parameters: parameters.clone(),
+                    body: body.clone(),
+                    env: Rc::clone(env),
+                };
+                env.borrow_mut().define(name, Value::Function(Rc::new(function)));
+                Ok(Value::Null)
+            },
+            Statement::While(condition, body) => {
+                while self.eval_expression(condition, env)?.is_truthy() {
+                    match self.eval_block(body, env) {
+                        Ok(_) | Err(Unwind::Continue) => {},
+                        Err(Unwind::Break) => break,
+                        Err(unwind) => return Err(unwind),
+                    }
+                }
+                Ok(Value::Null)
+            },
+            Statement::Break => Err(Unwind::Break),
+            Statement::Continue => Err(Unwind::Continue),
+        }
+    }
+
+    fn eval_expression(&mut self, expression: &Expression, env: &Env) -> Flow<Value> {
+        match expression {
+            Expression::Identifier(name, span) => Ok(env.borrow().get(name).ok_or_else(|| undefined_variable(name, *span))?),
+            Expression::IntegerLiteral(value) => Ok(Value::Integer(*value)),
+            Expression::FloatLiteral(value) => Ok(Value::Float(*value)),
+            Expression::StringLiteral(value) => Ok(Value::String(Rc::from(value.as_str()))),
+            Expression::Boolean(value) => Ok(Value::Boolean(*value)),
+            Expression::Prefix(operator, operand, span) => {
+                let operand = self.eval_expression(operand, env)?;
+                Ok(unary(operator, operand, *span)?)
+            },
+            // `&&` and `||` only evaluate their right side when it decides
+            // the result.
+            Expression::Infix(operator, left, right, _) if operator == "&&" || operator == "||" => {
+                let left = self.eval_expression(left, env)?.is_truthy();
+                if left == (operator == "||") {
+                    return Ok(Value::Boolean(left));
+                }
+                Ok(Value::Boolean(self.eval_expression(right, env)?.is_truthy()))
+            },
+            Expression::Infix(operator, left, right, span) => {
+                let left = self.eval_expression(left, env)?;
+                let right = self.eval_expression(right, env)?;
+                Ok(binary(operator, left, right, *span)?)
+            },
+            Expression::If(condition, consequence, alternative) => {
+                if self.eval_expression(condition, env)?.is_truthy() {
+                    self.eval_block(consequence, env)
+                } else if let Some(alternative) = alternative {
+                    self.eval_block(alternative, env)
+                } else {
+                    Ok(Value::Null)
+                }
+            },
+            Expression::Function(parameters, body) => Ok(Value::Function(Rc::new(Function {
+                name: None,
+                parameters: parameters.clone(),
+                body: body.clone(),
+                env: Rc::clone(env),
+            }))),
+            Expression::Call(function, arguments, span) => {
+                let function = self.eval_expression(function, env)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.eval_expression(argument, env))
+                    .collect::<Flow<Vec<Value>>>()?;
+                self.call(function, arguments, *span)
+            },
+            Expression::Assign(operator, name, value, span) => {
+                let value = self.eval_expression(value, env)?;
+                Ok(assign(env, operator, name, value, *span)?)
+            },
+        }
+    }
+
+    fn call(&mut self, function: Value, arguments: Vec<Value>, span: Span) -> Flow<Value> {
+        let function = match function {
+            Value::Function(function) => function,
+            Value::Builtin(builtin) => return Ok(call_builtin(&mut self.output, builtin, &arguments, span)?),
+            other => return Err(not_callable(&other, span).into()),
+        };
+        if arguments.len() != function.parameters.len() {
+            return Err(wrong_argument_count(&function.name, function.parameters.len(), arguments.len(), span).into());
+        }
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(stack_overflow(span).into());
+        }
+
+        let env = Environment::enclosed(&function.env);
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            env.borrow_mut().define(parameter, argument);
+        }
+        self.depth += 1;
+        let result = self.eval_statements(&function.body, &env);
+        self.depth -= 1;
+        match result {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Break) => Err(loop_control_outside_loop(&Statement::Break).into()),
+            Err(Unwind::Continue) => Err(loop_control_outside_loop(&Statement::Continue).into()),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+// Plain `=` stores the value; `+=` and the like combine it with the current
+// one first.
+fn assign(env: &Env, operator: &str, name: &str, mut value: Value, span: Span) -> Result<Value, Box<Diagnostic>> {
+    if let Some(operator) = operator.strip_suffix('=').filter(|operator| !operator.is_empty()) {
+        let current = env.borrow().get(name).ok_or_else(|| undefined_assignment(name, span))?;
+        value = binary(operator, current, value, span)?;
+    }
+    if !env.borrow_mut().assign(name, value.clone()) {
+        return Err(undefined_assignment(name, span));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::codes;
+    use crate::parser::parse_ok;
+
+    // Runs `input`, returning what it printed and the value or error message
+    // it finished with.
+    fn run(input: &str) -> (String, Result<Value, String>) {
+        let mut interpreter = Interpreter::with_output(Vec::new());
+        let result = interpreter.run(&parse_ok(input)).map_err(|error| error.to_string());
+        (String::from_utf8(interpreter.output().clone()).unwrap(), result)
+    }
+
+    fn value(input: &str) -> Value {
+        let (_, result) = run(input);
+        result.unwrap_or_else(|error| panic!("{}: {}", input, error))
+    }
+
+    fn error(input: &str) -> String {
+        let (_, result) = run(input);
+        result.expect_err(input)
+    }
+
+    #[test]
+    fn test_example_program() {
+        let (output, result) = run(include_str!("../example.nova"));
+        assert_eq!(result, Ok(Value::Null));
+        let mut expected = String::from("Result is: 5\nSum is: 79\n");
+        for i in 0..10 {
+            expected.push_str(&format!("Loop iteration: {}\n", i));
+        }
+        expected.push_str("x is greater than y\n");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_arithmetic_and_comparison() {
+        let cases = [
+            ("1 + 2 * 3 - 4 / 2;", Value::Integer(5)),
+            ("-7 / 2;", Value::Integer(-3)),
+            ("1 + 0.5;", Value::Float(1.5)),
+            ("7 / 2.0;", Value::Float(3.5)),
+            ("-(2.5);", Value::Float(-2.5)),
+            ("\"ab\" + \"cd\";", Value::String(Rc::from("abcd"))),
+            ("1 < 2 && 2 <= 2 && 3 > 2 && 3 >= 4 == false;", Value::Boolean(true)),
+            ("1 == 1.0;", Value::Boolean(true)),
+            ("\"a\" < \"b\";", Value::Boolean(true)),
+            ("1 == \"1\";", Value::Boolean(false)),
+            ("!0;", Value::Boolean(false)),
+            ("!!false;", Value::Boolean(false)),
+            ("false || 1;", Value::Boolean(true)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(value(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let (output, _) = run("fn loud(v) { print(v); return v; } false && loud(1); true || loud(2); true && loud(3);");
+        assert_eq!(output, "3\n");
+    }
+
+    #[test]
+    fn test_scopes_and_assignment() {
+        let cases = [
+            ("let a = 1; if (true) { let a = 2; } a;", Value::Integer(1)),
+            ("let a = 1; if (true) { a = 2; } a;", Value::Integer(2)),
+            ("let a = 1; a += 4; a *= 3; a -= 1; a /= 2; a;", Value::Integer(7)),
+            ("let s = \"a\"; s += \"b\"; s;", Value::String(Rc::from("ab"))),
+            ("let a = 1; let b = a = 5; a + b;", Value::Integer(10)),
+            ("let x = if (1 > 2) { 10 } else { 20 }; x;", Value::Integer(20)),
+            ("let x = if (false) { 10 }; x;", Value::Null),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(value(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_functions_and_closures() {
+        let cases = [
+            ("fn add(a, b) { return a + b; } add(2, 3);", Value::Integer(5)),
+            ("let twice = fn(f, x) { f(f(x)) }; twice(fn(n) { n * 3 }, 2);", Value::Integer(18)),
+            ("fn fib(n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); } fib(15);", Value::Integer(610)),
+            ("fn adder(n) { return fn(x) { x + n }; } let add5 = adder(5); add5(10);", Value::Integer(15)),
+            (
+                "fn counter() { let count = 0; return fn() { count += 1; count }; }
+                 let next = counter(); next(); next(); next();",
+                Value::Integer(3),
+            ),
+            ("fn early(n) { while (true) { if (n > 3) { return n; } n += 1; } } early(0);", Value::Integer(4)),
+            ("return 1; 2;", Value::Integer(1)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(value(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_loops() {
+        let input = "
+            let i = 0;
+            let total = 0;
+            while (i < 10) {
+                i += 1;
+                if (i == 3) { continue; }
+                if (i == 6) { break; }
+                total += i;
+            }
+            total;";
+        assert_eq!(value(input), Value::Integer(1 + 2 + 4 + 5));
+    }
+
+    #[test]
+    fn test_print_formats_values() {
+        let (output, _) = run("fn f() {} print(1, 2.0, true, \"s\", f, fn() {}, print, f());");
+        assert_eq!(output, "1 2.0 true s <fn f> <fn> <builtin print> null\n");
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let cases = [
+            ("let a = 1;\nb + a;", "2:1: error[E0201]: Undefined variable 'b'"),
+            ("c = 1;", "1:1: error[E0201]: Cannot assign to undefined variable 'c'"),
+            ("1 + true;", "1:3: error[E0202]: Cannot apply '+' to values of type integer and boolean"),
+            ("-\"s\";", "1:1: error[E0202]: Cannot apply '-' to a value of type string"),
+            ("let s = \"a\"; s -= 1;", "1:14: error[E0202]: Cannot apply '-' to values of type string and integer"),
+            ("10 / (5 - 5);", "1:4: error[E0203]: Division by zero"),
+            ("9223372036854775807 + 1;", "1:21: error[E0204]: Integer overflow computing 9223372036854775807 + 1"),
+            ("let m = -9223372036854775808; -m;", "1:31: error[E0204]: Integer overflow negating -9223372036854775808"),
+            ("let x = 5; x(1);", "1:12: error[E0205]: A value of type integer is not a function"),
+            ("fn f(a) { a } f(1, 2);", "1:15: error[E0206]: 'f' expects 1 argument, got 2"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(error(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_call_depth_is_limited() {
+        let thread = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+            let recurse = "fn down(n) { if (n == 0) { return 0; } return down(n - 1); }";
+            let deepest = value(&format!("{} down({});", recurse, MAX_CALL_DEPTH - 1));
+            (deepest.to_string(), error(&format!("{} down({});", recurse, MAX_CALL_DEPTH)))
+        });
+        let (deepest, too_deep) = thread.unwrap().join().unwrap();
+        assert_eq!(deepest, "0");
+        assert_eq!(too_deep, "1:47: error[E0207]: Maximum call depth exceeded");
+    }
+
+    #[test]
+    fn test_errors_stop_execution() {
+        let (output, result) = run("print(1); print(missing); print(2);");
+        assert_eq!(output, "1\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_loop_control_outside_loops_is_an_error() {
+        let mut interpreter = Interpreter::with_output(Vec::new());
+        let program = Program { statements: vec![Statement::Break] };
+        assert_eq!(interpreter.run(&program).unwrap_err().code, codes::LOOP_CONTROL_OUTSIDE_LOOP);
+        let mut program = parse_ok("fn f() { 1 } f();");
+        let Statement::Function(_, _, body, _) = &mut program.statements[0] else { unreachable!() };
+        body.push(Statement::Continue);
+        assert_eq!(interpreter.run(&program).unwrap_err().message, "'continue' outside of a loop");
+    }
+
+    #[test]
+    fn test_globals_persist_across_runs() {
+        let mut interpreter = Interpreter::with_output(Vec::new());
+        for (input, expected) in [("let a = 2;", Value::Null), ("fn sq(x) { x * x }", Value::Null), ("sq(a) + 1;", Value::Integer(5))] {
+            assert_eq!(interpreter.run(&parse_ok(input)), Ok(expected), "input: {}", input);
+        }
+    }
+}
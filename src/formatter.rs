@@ -0,0 +1,304 @@
+// src/formatter.rs
+
+use crate::cst::{self, SyntaxElement, SyntaxKind, SyntaxNode};
+use crate::token::{Token, TokenType, TriviaKind};
+
+const INDENT: &str = "    ";
+
+// Reprints a program in the standard layout: one statement per line, blocks
+// indented by four spaces with `} else {` kept together, single spaces
+// around binary operators and after commas, and at most one blank line in a
+// row. Comments are kept where they were, either on their own line or
+// trailing a line. Works from the CST so no comment is lost. Input the
+// parser rejects still comes back laid out as well as its structure allows,
+// but `fmt` refuses it, since a typo could be reflowed into something that
+// means something else.
+pub fn format(input: &str) -> String {
+    let tree = cst::parse(input);
+    let mut formatter = Formatter::default();
+    formatter.node(&tree);
+    let mut out = formatter.out;
+    out.truncate(out.trim_end().len());
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    depth: usize,
+    // The last token written in the current statement, and whether it was
+    // a prefix operator.
+    previous: Option<TokenType>,
+    unary: bool,
+    // Whether what comes next must start a new line, and whether it is
+    // still part of the statement started on an earlier line.
+    break_line: bool,
+    in_statement: bool,
+    after_line_comment: bool,
+    after_block_comment: bool,
+}
+
+impl Formatter {
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind {
+            SyntaxKind::Root => {
+                for child in &node.children {
+                    match child {
+                        SyntaxElement::Node(statement) => self.statement(statement),
+                        SyntaxElement::Token(token) => {
+                            self.comments(token);
+                        },
+                    }
+                }
+            },
+            SyntaxKind::Block => self.block(node),
+            SyntaxKind::Statement | SyntaxKind::Group => {
+                for child in &node.children {
+                    match child {
+                        SyntaxElement::Node(node) => self.node(node),
+                        SyntaxElement::Token(token) => self.token(token),
+                    }
+                }
+            },
+        }
+    }
+
+    fn statement(&mut self, statement: &SyntaxNode) {
+        self.break_line = true;
+        self.in_statement = false;
+        self.previous = None;
+        self.node(statement);
+        self.in_statement = false;
+    }
+
+    // `{}` stays on one line when there is nothing inside it; otherwise the
+    // statements go on lines of their own, one level deeper.
+    fn block(&mut self, block: &SyntaxNode) {
+        let mut statements = Vec::new();
+        let mut tokens = Vec::new();
+        for child in &block.children {
+            match child {
+                SyntaxElement::Node(statement) => statements.push(statement),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        let (open, close) = (tokens.first(), tokens.get(1));
+        if let Some(open) = open {
+            self.token(open);
+        }
+        let empty = statements.is_empty() && close.is_none_or(|close| !has_comments(close));
+        if empty {
+            if let Some(close) = close {
+                self.token(close);
+            }
+            return;
+        }
+        self.depth += 1;
+        for statement in statements {
+            self.statement(statement);
+        }
+        // Comments before the `}` belong to the block, not to a statement
+        // continuing from the `{`.
+        self.in_statement = false;
+        let newlines = close.map_or(0, |close| self.comments(close));
+        self.depth -= 1;
+        self.break_line = true;
+        self.in_statement = false;
+        if let Some(close) = close {
+            self.word(close, newlines);
+        }
+    }
+
+    fn token(&mut self, token: &Token) {
+        let newlines = self.comments(token);
+        self.word(token, newlines);
+    }
+
+    // Writes the comments before `token`, returning how many line breaks
+    // came between the last of them (or the previous token) and the token.
+    fn comments(&mut self, token: &Token) -> usize {
+        let mut newlines = 0;
+        for trivia in &token.leading_trivia {
+            match trivia.kind {
+                TriviaKind::Whitespace => newlines += trivia.text.matches('\n').count(),
+                TriviaKind::LineComment | TriviaKind::BlockComment => {
+                    if newlines == 0 && !self.out.is_empty() && !self.after_line_comment {
+                        self.out.push(' ');
+                    } else {
+                        self.line(newlines, false);
+                    }
+                    self.out.push_str(&trivia.text);
+                    self.after_line_comment = trivia.kind == TriviaKind::LineComment;
+                    self.after_block_comment = trivia.kind == TriviaKind::BlockComment;
+                    self.break_line |= self.after_line_comment;
+                    newlines = 0;
+                },
+            }
+        }
+        newlines
+    }
+
+    fn word(&mut self, token: &Token, newlines: usize) {
+        if token.token_type == TokenType::EOF {
+            return;
+        }
+        if self.break_line || (self.after_block_comment && newlines > 0) {
+            // An `else` pushed onto a line of its own by a comment lines up
+            // with the `}` before it.
+            if token.token_type == TokenType::Else {
+                self.in_statement = false;
+            }
+            self.line(newlines, token.token_type == TokenType::RBrace);
+        } else if self.space_before(&token.token_type) {
+            self.out.push(' ');
+        }
+        self.out.push_str(&token.literal);
+
+        self.unary = match token.token_type {
+            TokenType::Bang => true,
+            TokenType::Minus => !self.previous.as_ref().is_some_and(ends_operand),
+            _ => false,
+        };
+        self.after_block_comment = false;
+        self.break_line = matches!(token.token_type, TokenType::DocComment(_));
+        self.in_statement = !self.break_line;
+        self.previous = Some(token.token_type.clone());
+    }
+
+    fn space_before(&self, token_type: &TokenType) -> bool {
+        let Some(previous) = &self.previous else {
+            return !self.out.is_empty() && !self.out.ends_with('\n');
+        };
+        match (previous, token_type) {
+            _ if self.unary => false,
+            (_, TokenType::RParen | TokenType::Comma | TokenType::Semicolon) => false,
+            (TokenType::LParen, _) => false,
+            (TokenType::Ident(_) | TokenType::RParen | TokenType::RBrace | TokenType::Fn | TokenType::Function, TokenType::LParen) => false,
+            (TokenType::LBrace, TokenType::RBrace) => false,
+            _ => true,
+        }
+    }
+
+    // Starts a new line, keeping one blank line where the source had any
+    // between statements.
+    fn line(&mut self, newlines: usize, closing: bool) {
+        self.break_line = false;
+        self.after_line_comment = false;
+        if self.out.is_empty() {
+            return;
+        }
+        self.out.truncate(self.out.trim_end_matches([' ', '\t']).len());
+        self.out.push('\n');
+        let after_open = self.out.trim_end().ends_with('{');
+        if newlines >= 2 && !after_open && !closing {
+            self.out.push('\n');
+        }
+        let depth = self.depth + usize::from(self.in_statement);
+        for _ in 0..depth {
+            self.out.push_str(INDENT);
+        }
+    }
+}
+
+// Whether a `-` after this token subtracts rather than negates.
+fn ends_operand(token_type: &TokenType) -> bool {
+    matches!(token_type,
+        TokenType::Ident(_) | TokenType::Int(_) | TokenType::Float(_) | TokenType::Str(_) | TokenType::Bool(_)
+        | TokenType::True | TokenType::False | TokenType::RParen | TokenType::RBrace)
+}
+
+fn has_comments(token: &Token) -> bool {
+    token.leading_trivia.iter().any(|trivia| trivia.kind != TriviaKind::Whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_ok;
+
+    fn program(input: &str) -> String {
+        parse_ok(input).to_string()
+    }
+
+    // Formatting must keep the meaning and be stable once applied.
+    fn assert_formats(input: &str, expected: &str) {
+        let formatted = format(input);
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted), formatted, "formatting is not idempotent");
+        assert_eq!(program(&formatted), program(input));
+    }
+
+    #[test]
+    fn test_spacing_and_indentation() {
+        assert_formats(
+            "let x=1+2*-y;fn add( a,b ){return a+b;}\nif(x>1){print(add(x,!true));}else{ }",
+            "let x = 1 + 2 * -y;\nfn add(a, b) {\n    return a + b;\n}\nif (x > 1) {\n    print(add(x, !true));\n} else {}\n",
+        );
+        assert_formats(
+            "let f=fn(a){a-1};while(i<3){i+=1;if(i==2){continue;}}",
+            "let f = fn(a) {\n    a - 1\n};\nwhile (i < 3) {\n    i += 1;\n    if (i == 2) {\n        continue;\n    }\n}\n",
+        );
+    }
+
+    #[test]
+    fn test_calls_of_function_literals() {
+        assert_formats("print(fn() { 2 }());", "print(fn() {\n    2\n}());\n");
+    }
+
+    #[test]
+    fn test_blank_lines_collapse() {
+        assert_formats(
+            "\n\nlet a = 1;\n\n\n\nlet b = 2;\nfn f() {\n\n    a\n\n}\n\n",
+            "let a = 1;\n\nlet b = 2;\nfn f() {\n    a\n}\n",
+        );
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        assert_formats(
+            "// header\nlet a = 1; // trailing\n/* block */ let b = 2;\nfn f() { // after brace\n  /// doc\n  let c = 3;\n  // last\n}\n/* end */",
+            "// header\nlet a = 1; // trailing\n/* block */ let b = 2;\nfn f() { // after brace\n    /// doc\n    let c = 3;\n    // last\n}\n/* end */\n",
+        );
+    }
+
+    #[test]
+    fn test_comment_only_blocks() {
+        assert_formats("fn f() {\n// only\n}", "fn f() {\n    // only\n}\n");
+        assert_formats("if (x) { /* only */ }", "if (x) { /* only */\n}\n");
+    }
+
+    #[test]
+    fn test_else_after_a_comment() {
+        assert_formats("if (a) { b; } // c\nelse { d; }", "if (a) {\n    b;\n} // c\nelse {\n    d;\n}\n");
+        assert_formats(
+            "fn f() { let v = if (a) { b } /* c */\nelse { d }; v }",
+            "fn f() {\n    let v = if (a) {\n        b\n    } /* c */\n    else {\n        d\n    };\n    v\n}\n",
+        );
+    }
+
+    #[test]
+    fn test_line_comment_inside_expression() {
+        assert_formats("let a = 1 + // one\n2;", "let a = 1 + // one\n    2;\n");
+    }
+
+    #[test]
+    fn test_sample_programs_are_stable() {
+        let inputs = [
+            include_str!("../example.nova"),
+            include_str!("../samples/closures.nova"),
+            include_str!("../samples/recursion.nova"),
+            include_str!("../samples/loops.nova"),
+            include_str!("../samples/values.nova"),
+            include_str!("../samples/scopes.nova"),
+        ];
+        for input in inputs {
+            let formatted = format(input);
+            assert_eq!(format(&formatted), formatted);
+            assert_eq!(program(&formatted), program(input));
+        }
+        assert_eq!(format(include_str!("../example.nova")), include_str!("../example.nova"));
+    }
+}
@@ -1,114 +1,346 @@
 // src/lexer.rs
 
-use crate::token::{Token, TokenType};
+use std::borrow::Cow;
+use std::io::{self, Read};
 
-pub struct Lexer {
-    input: String,
+use crate::token::{LexError, Span, Token, TokenType, Trivia, TriviaKind};
+
+// Bytes of already-lexed text a streaming lexer lets pile up before dropping
+// them from its buffer.
+const DISCARD_THRESHOLD: usize = 64 * 1024;
+
+pub struct Lexer<'a> {
+    input: Cow<'a, str>,
+    // Byte offset of `input[0]` in the whole source. Only a lexer reading
+    // from an `io::Read` drops text it is done with, so it is 0 otherwise.
+    base: usize,
+    reader: Option<Box<dyn Read + 'a>>,
+    // Bytes read from `reader` that do not yet form a complete UTF-8 char.
+    pending: Vec<u8>,
+    io_error: Option<io::Error>,
     position: usize,
     read_position: usize,
     ch: char,
+    line: usize,
+    column: usize,
+    lossless: bool,
+    trivia: Vec<Trivia>,
+    finished: bool,
 }
 
-impl Lexer {
-    pub fn new(input: String) -> Self {
+impl<'a> Lexer<'a> {
+    // Takes either an owned `String` or a borrowed `&str`, which is lexed in
+    // place without copying.
+    pub fn new(input: impl Into<Cow<'a, str>>) -> Self {
         let mut lexer = Lexer {
-            input,
+            input: input.into(),
+            base: 0,
+            reader: None,
+            pending: Vec::new(),
+            io_error: None,
             position: 0,
             read_position: 0,
             ch: '\0',
+            line: 1,
+            column: 1,
+            lossless: false,
+            trivia: Vec::new(),
+            finished: false,
         };
         lexer.read_char();
         lexer
     }
-    fn peek_char(&self) -> char {
-        if self.read_position >= self.input.len() {
-            '\0'
-        } else {
-            self.input.chars().nth(self.read_position).unwrap_or('\0')
-        }
+
+    // A lexer that keeps whitespace and comments as the `leading_trivia` of
+    // the token that follows them, so the source can be rebuilt exactly.
+    pub fn new_lossless(input: impl Into<Cow<'a, str>>) -> Self {
+        let mut lexer = Lexer::new(input);
+        lexer.lossless = true;
+        lexer
+    }
+
+    // Lexes a source as it is read, keeping only the text of the current
+    // token in memory. Invalid UTF-8 is replaced with U+FFFD; a read error
+    // ends the input with a `LexError::Io` token before EOF.
+    pub fn from_reader(reader: impl Read + 'a) -> Self {
+        let mut lexer = Lexer::new(String::new());
+        lexer.reader = Some(Box::new(reader));
+        lexer.read_char();
+        lexer
+    }
+
+    // `position` and `read_position` are byte offsets into the source; each
+    // step decodes a single char from `read_position`, so lexing stays linear.
+    fn peek_char(&mut self) -> char {
+        self.peek_char_at(0)
+    }
+
+    fn peek_char_at(&mut self, n: usize) -> char {
+        self.fill((n + 1) * 4);
+        self.input[self.read_position - self.base..].chars().nth(n).unwrap_or('\0')
     }
 
     fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = '\0';
-        } else {
-            self.ch = self.input.chars().nth(self.read_position).unwrap();
+        if self.read_position > self.position {
+            if self.ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
         }
         self.position = self.read_position;
-        self.read_position += 1;
+        self.fill(4);
+        match self.input[self.read_position - self.base..].chars().next() {
+            Some(ch) => {
+                self.ch = ch;
+                self.read_position += ch.len_utf8();
+            }
+            None => self.ch = '\0',
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.position - self.base >= self.input.len() && self.reader.is_none()
+    }
+
+    // Source text between two absolute byte offsets of the current token.
+    fn slice(&self, start: usize, end: usize) -> &str {
+        &self.input[start - self.base..end - self.base]
     }
+
+    // Reads from the source until at least `bytes` bytes follow
+    // `read_position` in the buffer, or the reader is exhausted.
+    fn fill(&mut self, bytes: usize) {
+        while self.reader.is_some() && self.input.len() - (self.read_position - self.base) < bytes {
+            let Some(reader) = self.reader.as_mut() else {
+                return;
+            };
+            let mut chunk = [0; 8192];
+            match reader.read(&mut chunk) {
+                Ok(0) => {
+                    let rest = String::from_utf8_lossy(&self.pending).into_owned();
+                    self.input.to_mut().push_str(&rest);
+                    self.pending.clear();
+                    self.reader = None;
+                }
+                Ok(count) => {
+                    self.pending.extend_from_slice(&chunk[..count]);
+                    self.decode_pending();
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.io_error = Some(e);
+                    self.reader = None;
+                }
+            }
+        }
+    }
+
+    fn decode_pending(&mut self) {
+        let buffer = self.input.to_mut();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    buffer.push_str(text);
+                    self.pending.clear();
+                    return;
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    buffer.push_str(std::str::from_utf8(&self.pending[..valid]).expect("valid prefix"));
+                    match e.error_len() {
+                        Some(len) => {
+                            buffer.push(char::REPLACEMENT_CHARACTER);
+                            self.pending.drain(..valid + len);
+                        }
+                        // A char split across reads; wait for the rest.
+                        None => {
+                            self.pending.drain(..valid);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Called between tokens: a streaming lexer drops the text before the
+    // current position once enough of it has built up.
+    fn discard_consumed(&mut self) {
+        let consumed = self.position - self.base;
+        if self.reader.is_some() && consumed >= DISCARD_THRESHOLD {
+            self.input.to_mut().drain(..consumed);
+            self.base = self.position;
+        }
+    }
+
     fn skip_comment(&mut self) {
-        while self.ch != '\n' && self.ch != '\0' {
+        while self.ch != '\n' && !self.at_end() {
             self.read_char();
         }
     }
-    fn read_string(&mut self) -> String {
-        let start_position = self.position + 1; 
+    // Decodes the string starting at the opening quote. The whole literal is
+    // always consumed so lexing resumes after it even when an escape is bad;
+    // the first problem found is reported.
+    fn read_string(&mut self) -> Result<String, LexError> {
+        let mut value = String::new();
+        let mut error = None;
         loop {
             self.read_char();
-            if self.ch == '"' || self.ch == '\0' { 
-                break;
+            match self.ch {
+                '"' => break,
+                '\0' if self.at_end() => return Err(LexError::UnterminatedString),
+                '\\' => match self.read_escape() {
+                    Ok(ch) => value.push(ch),
+                    Err(LexError::UnterminatedString) => return Err(LexError::UnterminatedString),
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                },
+                ch => value.push(ch),
             }
         }
-        let end_position = self.position;
-        if self.ch == '"' {
+        self.read_char();
+        match error {
+            Some(e) => Err(e),
+            None => Ok(value),
+        }
+    }
+
+    // Called with `ch` on the backslash; leaves `ch` on the last char of the
+    // escape sequence.
+    fn read_escape(&mut self) -> Result<char, LexError> {
+        let start_position = self.position;
+        self.read_char();
+        let decoded = match self.ch {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            'u' => self.read_unicode_escape(),
+            '\0' if self.at_end() => return Err(LexError::UnterminatedString),
+            _ => None,
+        };
+        decoded.ok_or_else(|| LexError::InvalidEscape(self.slice(start_position, self.read_position).to_string()))
+    }
+
+    // `\u{...}` with one to six hex digits naming a Unicode scalar value.
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if self.peek_char() != '{' {
+            return None;
+        }
+        self.read_char();
+        let digits_start = self.read_position;
+        while self.peek_char().is_ascii_hexdigit() {
             self.read_char();
         }
-        self.input[start_position..end_position].to_string()
+        let digits_end = self.read_position;
+        if self.peek_char() != '}' {
+            return None;
+        }
+        self.read_char();
+        let digits = self.slice(digits_start, digits_end);
+        if digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+        u32::from_str_radix(digits, 16).ok().and_then(char::from_u32)
     }
-    fn skip_multi_line_comment(&mut self) {
+
+    // Called on the opening `/*`. Block comments nest, so commenting out code
+    // that already contains one works; returns false if the input ends
+    // before every opened comment is closed.
+    fn skip_multi_line_comment(&mut self) -> bool {
+        let mut depth = 0;
         loop {
-            self.read_char();
-            if self.ch == '*' && self.peek_char() == '/' {
-                self.read_char(); 
-                self.read_char(); 
-                break;
-            } else if self.ch == '\0' {
-                break;
+            match (self.ch, self.peek_char()) {
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                    self.read_char();
+                },
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char();
+                    self.read_char();
+                    if depth == 0 {
+                        return true;
+                    }
+                },
+                ('\0', _) if self.at_end() => return false,
+                _ => self.read_char(),
             }
         }
     }
-    
+
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
-        if self.ch == '/' {
-            if self.peek_char() == '/' {
+        self.discard_consumed();
+        loop {
+            let start = self.position;
+            let line = self.line;
+            let column = self.column;
+            self.skip_whitespace();
+            if self.position > start {
+                self.push_trivia(TriviaKind::Whitespace, start, line, column);
+                continue;
+            }
+            if self.ch == '/' && self.peek_char() == '/' {
+                // `///` starts a doc comment, but `////` is an ordinary one.
+                let is_doc = self.peek_char_at(1) == '/' && self.peek_char_at(2) != '/';
                 self.skip_comment();
-                self.read_char();
-                return self.next_token();
-            } else if self.peek_char() == '*' {
-                self.skip_multi_line_comment();
-                return self.next_token();
+                if is_doc {
+                    let text = self.slice(start + 3, self.position).to_string();
+                    return self.token_from(TokenType::DocComment(text), start, line, column);
+                }
+                self.push_trivia(TriviaKind::LineComment, start, line, column);
+            } else if self.ch == '/' && self.peek_char() == '*' {
+                // Likewise `/**` but not `/***` or the empty comment `/**/`.
+                let is_doc = self.peek_char_at(1) == '*' && !matches!(self.peek_char_at(2), '*' | '/');
+                if !self.skip_multi_line_comment() {
+                    let literal = self.slice(start, self.position).to_string();
+                    let span = Span { start, end: start + 2, line, column };
+                    return self.make_token(TokenType::Error(LexError::UnterminatedComment), literal, span);
+                }
+                if is_doc {
+                    let text = self.slice(start + 3, self.position - 2).to_string();
+                    return self.token_from(TokenType::DocComment(text), start, line, column);
+                }
+                self.push_trivia(TriviaKind::BlockComment, start, line, column);
+            } else {
+                break;
             }
         }
-        
-        let token = match self.ch {
-            '+' => Token { token_type: TokenType::Plus, literal: self.ch.to_string() },
-            '-' => Token { token_type: TokenType::Minus, literal: self.ch.to_string() },
-            '*' => Token { token_type: TokenType::Asterisk, literal: self.ch.to_string() },
-            '/' => Token { token_type: TokenType::Slash, literal: self.ch.to_string() },
-            '=' => Token { token_type: TokenType::Equal, literal: self.ch.to_string() },
-            '!' => Token { token_type: TokenType::NotEqual, literal: self.ch.to_string() },
-            '<' => Token { token_type: TokenType::LessThan, literal: self.ch.to_string() },
-            '>' => Token { token_type: TokenType::GreaterThan, literal: self.ch.to_string() },
-            '(' => Token { token_type: TokenType::LParen, literal: self.ch.to_string() },
-            ')' => Token { token_type: TokenType::RParen, literal: self.ch.to_string() },
-            '{' => Token { token_type: TokenType::LBrace, literal: self.ch.to_string() },
-            '}' => Token { token_type: TokenType::RBrace, literal: self.ch.to_string() },
-            ',' => Token { token_type: TokenType::Comma, literal: self.ch.to_string() },
-            ';' => Token { token_type: TokenType::Semicolon, literal: self.ch.to_string() },
+
+        let start = self.position;
+        let line = self.line;
+        let column = self.column;
+        let (token_type, literal) = match self.ch {
+            '+' => self.read_operator('=', TokenType::PlusAssign, TokenType::Plus),
+            '-' => self.read_operator('=', TokenType::MinusAssign, TokenType::Minus),
+            '*' => self.read_operator('=', TokenType::AsteriskAssign, TokenType::Asterisk),
+            '/' => self.read_operator('=', TokenType::SlashAssign, TokenType::Slash),
+            '=' => self.read_operator('=', TokenType::Eq, TokenType::Assign),
+            '!' => self.read_operator('=', TokenType::NotEq, TokenType::Bang),
+            '<' => self.read_operator('=', TokenType::LessEq, TokenType::LessThan),
+            '>' => self.read_operator('=', TokenType::GreaterEq, TokenType::GreaterThan),
+            '&' => self.read_operator('&', TokenType::And, TokenType::Illegal),
+            '|' => self.read_operator('|', TokenType::Or, TokenType::Illegal),
+            '(' => self.read_single(TokenType::LParen),
+            ')' => self.read_single(TokenType::RParen),
+            '{' => self.read_single(TokenType::LBrace),
+            '}' => self.read_single(TokenType::RBrace),
+            ',' => self.read_single(TokenType::Comma),
+            ';' => self.read_single(TokenType::Semicolon),
             '"' => {
-                let literal = self.read_string();
-                return Token { token_type: TokenType::Str(literal.clone()), literal };
-            },            
-            
-            '0'..='9' => {
-            let literal = self.read_number();
-                return Token { 
-                    token_type: TokenType::Int(literal.parse::<i64>().expect("Failed to parse integer")),
-                    literal 
+                let token_type = match self.read_string() {
+                    Ok(value) => TokenType::Str(value),
+                    Err(e) => TokenType::Error(e),
                 };
+                (token_type, self.slice(start, self.position).to_string())
             },
+            '0'..='9' => self.read_number(),
             _ if Lexer::is_letter(self.ch) => {
                 let literal = self.read_identifier();
                 let token_type = match literal.as_str() {
@@ -120,26 +352,139 @@ impl Lexer {
                     "true" => TokenType::True,
                     "false" => TokenType::False,
                     "function" => TokenType::Function,
+                    "while" => TokenType::While,
+                    "break" => TokenType::Break,
+                    "continue" => TokenType::Continue,
                     _ => TokenType::Ident(literal.clone()),
                 };
-                return Token { token_type, literal };
+                (token_type, literal)
             },
-            '\0' => Token { 
-                token_type: TokenType::EOF, 
-                literal: "".to_string() 
+            '\0' if self.at_end() => match self.io_error.take() {
+                Some(e) => (TokenType::Error(LexError::Io(e.to_string())), "".to_string()),
+                None => (TokenType::EOF, "".to_string()),
             },
-            _ => Token { token_type: TokenType::Illegal, literal: self.ch.to_string() },
+            _ => self.read_single(TokenType::Illegal),
         };
-    self.read_char();
-    token
-}
+        self.make_token(token_type, literal, Span { start, end: self.position, line, column })
+    }
+
+    fn make_token(&mut self, token_type: TokenType, literal: String, span: Span) -> Token {
+        Token {
+            token_type,
+            literal,
+            span,
+            leading_trivia: std::mem::take(&mut self.trivia),
+        }
+    }
+
+    fn push_trivia(&mut self, kind: TriviaKind, start: usize, line: usize, column: usize) {
+        if self.lossless {
+            self.trivia.push(Trivia {
+                kind,
+                text: self.slice(start, self.position).to_string(),
+                span: Span { start, end: self.position, line, column },
+            });
+        }
+    }
+
+    fn token_from(&mut self, token_type: TokenType, start: usize, line: usize, column: usize) -> Token {
+        let literal = self.slice(start, self.position).to_string();
+        self.make_token(token_type, literal, Span { start, end: self.position, line, column })
+    }
+
+    fn read_single(&mut self, token_type: TokenType) -> (TokenType, String) {
+        let literal = self.ch.to_string();
+        self.read_char();
+        (token_type, literal)
+    }
+
+    // Reads a two-character operator when the next char is `second`, falling
+    // back to the single-character token otherwise.
+    fn read_operator(&mut self, second: char, double: TokenType, single: TokenType) -> (TokenType, String) {
+        if self.peek_char() != second {
+            return self.read_single(single);
+        }
+        let start_position = self.position;
+        self.read_char();
+        self.read_char();
+        (double, self.slice(start_position, self.position).to_string())
+    }
 
-    fn read_number(&mut self) -> String {
+    // Numbers are decimal (`42`, `1_000_000`), floating point (`3.14`,
+    // `1e-9`, `2.5E+3`) or prefixed integers (`0xFF`, `0b1010`, `0o755`).
+    // Underscores may separate digits anywhere after the first one.
+    fn read_number(&mut self) -> (TokenType, String) {
         let start_position = self.position;
-        while self.ch.is_digit(10) {
+        let radix = match (self.ch, self.peek_char()) {
+            ('0', 'x' | 'X') => 16,
+            ('0', 'b' | 'B') => 2,
+            ('0', 'o' | 'O') => 8,
+            _ => 10,
+        };
+        let mut valid = true;
+        let mut is_float = false;
+        if radix == 10 {
+            self.read_digits(10);
+            if self.ch == '.' && self.peek_char().is_ascii_digit() {
+                is_float = true;
+                self.read_char();
+                self.read_digits(10);
+            }
+            if matches!(self.ch, 'e' | 'E') {
+                is_float = true;
+                self.read_char();
+                if matches!(self.ch, '+' | '-') {
+                    self.read_char();
+                }
+                valid = self.read_digits(10) > 0;
+            }
+        } else {
+            self.read_char();
+            self.read_char();
+            valid = self.read_digits(radix) > 0;
+        }
+        // Letters or digits glued onto the literal, as in `0b102` or `12ab`,
+        // belong to it and make the whole literal invalid.
+        while self.ch.is_alphanumeric() || self.ch == '_' {
+            valid = false;
             self.read_char();
         }
-        self.input[start_position..self.position].to_string()
+
+        let literal = self.slice(start_position, self.position).to_string();
+        if !valid {
+            return (TokenType::Error(LexError::InvalidNumber(literal.clone())), literal);
+        }
+        let digits: String = literal.chars().filter(|&ch| ch != '_').collect();
+        // Too large a float would become infinity; too small a one rounds
+        // towards zero, as it would in arithmetic.
+        if is_float {
+            let token_type = match digits.parse::<f64>().expect("Failed to parse float") {
+                value if value.is_finite() => TokenType::Float(value),
+                _ => TokenType::Error(LexError::FloatOverflow(literal.clone())),
+            };
+            return (token_type, literal);
+        }
+        let digits = if radix == 10 { &digits[..] } else { &digits[2..] };
+        // Anything above the magnitude of `i64::MIN` can never fit, whatever
+        // its sign; the parser rejects 2^63 itself unless it is negated.
+        let token_type = match u64::from_str_radix(digits, radix) {
+            Ok(value) if value <= i64::MIN.unsigned_abs() => TokenType::Int(value),
+            _ => TokenType::Error(LexError::IntegerOverflow(literal.clone())),
+        };
+        (token_type, literal)
+    }
+
+    // Consumes digits of the given radix and `_` separators, returning how
+    // many digits were read.
+    fn read_digits(&mut self, radix: u32) -> usize {
+        let mut count = 0;
+        while self.ch.is_digit(radix) || self.ch == '_' {
+            if self.ch != '_' {
+                count += 1;
+            }
+            self.read_char();
+        }
+        count
     }
 
     fn skip_whitespace(&mut self) {
@@ -148,18 +493,41 @@ impl Lexer {
         }
     }
 
+    // Identifiers start with a letter or `_` and continue with letters,
+    // digits or `_`. "Letter" and "digit" are Unicode's alphabetic and
+    // numeric properties, a close approximation of XID_Start/XID_Continue.
+    // Keywords are matched against the whole identifier, so `letter` and
+    // `iffy` are plain names.
     fn read_identifier(&mut self) -> String {
         let start_position = self.position;
-        while Lexer::is_letter(self.ch) {
+        while Lexer::is_identifier_continue(self.ch) {
             self.read_char();
         }
-        self.input[start_position..self.position].to_string()
+        self.slice(start_position, self.position).to_string()
     }
 
     fn is_letter(ch: char) -> bool {
         ch.is_alphabetic() || ch == '_'
     }
+
+    fn is_identifier_continue(ch: char) -> bool {
+        ch.is_alphanumeric() || ch == '_'
+    }
+}
+// Yields every token up to and including EOF, then ends.
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        self.finished = token.token_type == TokenType::EOF;
+        Some(token)
+    }
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -176,7 +544,7 @@ mod tests {
         let mut lexer = Lexer::new(input.to_string());
         let expected_tokens = vec![
             TokenType::Plus, TokenType::Minus, TokenType::Asterisk, TokenType::Slash,
-            TokenType::Equal, TokenType::NotEqual, TokenType::LessThan, TokenType::GreaterThan,
+            TokenType::Assign, TokenType::Bang, TokenType::LessThan, TokenType::GreaterThan,
         ];
 
         for expected in expected_tokens {
@@ -185,6 +553,36 @@ mod tests {
         }
     }
     #[test]
+    fn test_two_character_operators() {
+        let input = "== != <= >= && || =!= <<= a==b";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected_tokens = [
+            (TokenType::Eq, "=="), (TokenType::NotEq, "!="), (TokenType::LessEq, "<="),
+            (TokenType::GreaterEq, ">="), (TokenType::And, "&&"), (TokenType::Or, "||"),
+            (TokenType::Assign, "="), (TokenType::NotEq, "!="),
+            (TokenType::LessThan, "<"), (TokenType::LessEq, "<="),
+            (TokenType::Ident("a".to_string()), "a"), (TokenType::Eq, "=="), (TokenType::Ident("b".to_string()), "b"),
+        ];
+        for (expected, literal) in expected_tokens.iter() {
+            let token = lexer.next_token();
+            assert_eq!(&token.token_type, expected);
+            assert_eq!(token.literal, *literal);
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_compound_assignment_operators() {
+        let input = "+= -= *= /= + =";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected_tokens = [
+            TokenType::PlusAssign, TokenType::MinusAssign, TokenType::AsteriskAssign,
+            TokenType::SlashAssign, TokenType::Plus, TokenType::Assign, TokenType::EOF,
+        ];
+        for expected in expected_tokens.iter() {
+            assert_eq!(&lexer.next_token().token_type, expected);
+        }
+    }
+    #[test]
     fn test_numbers() {
         let input = "123 456 789";
         let mut lexer = Lexer::new(input.to_string());
@@ -200,6 +598,78 @@ mod tests {
         }
     }
     #[test]
+    fn test_number_literals() {
+        let input = "2.75 1e-9 2.5E+3 6e2 0xFF 0Xff 0b1010 0o755 1_000_000 0x_dead_BEEF 1_0.2_5 0";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            TokenType::Float(2.75), TokenType::Float(1e-9), TokenType::Float(2500.0), TokenType::Float(600.0),
+            TokenType::Int(255), TokenType::Int(255), TokenType::Int(10), TokenType::Int(493),
+            TokenType::Int(1_000_000), TokenType::Int(0xdead_beef), TokenType::Float(10.25), TokenType::Int(0),
+        ];
+        for (expected, literal) in expected.iter().zip(input.split(' ')) {
+            let token = lexer.next_token();
+            assert_eq!(&token.token_type, expected);
+            assert_eq!(token.literal, literal);
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_number_followed_by_operator() {
+        let input = "1.5+2 3.foo";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            TokenType::Float(1.5), TokenType::Plus, TokenType::Int(2),
+            TokenType::Int(3), TokenType::Illegal, TokenType::Ident("foo".to_string()),
+        ];
+        for expected in expected.iter() {
+            assert_eq!(&lexer.next_token().token_type, expected);
+        }
+    }
+    #[test]
+    fn test_invalid_number_literals() {
+        for input in ["0x", "0b102", "0o8", "1e", "2.5e+", "12abc", "0xFG"] {
+            let mut lexer = Lexer::new(format!("{};", input));
+            let token = lexer.next_token();
+            assert_eq!(token.token_type, TokenType::Error(LexError::InvalidNumber(input.to_string())), "input: {}", input);
+            assert_eq!(token.span.end, input.len());
+            assert_eq!(lexer.next_token().token_type, TokenType::Semicolon);
+        }
+    }
+    #[test]
+    fn test_integer_overflow() {
+        let input = "9223372036854775807 9223372036854775808 9223372036854775809 99999999999999999999 0xFFFFFFFFFFFFFFFF 1";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            TokenType::Int(9223372036854775807),
+            TokenType::Int(9223372036854775808),
+            TokenType::Error(LexError::IntegerOverflow("9223372036854775809".to_string())),
+            TokenType::Error(LexError::IntegerOverflow("99999999999999999999".to_string())),
+            TokenType::Error(LexError::IntegerOverflow("0xFFFFFFFFFFFFFFFF".to_string())),
+            TokenType::Int(1),
+        ];
+        for expected in expected.iter() {
+            assert_eq!(&lexer.next_token().token_type, expected);
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_float_overflow() {
+        let input = "1.7976931348623157e308 1e309 1e999 2_0e400 1e-999 1";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            TokenType::Float(f64::MAX),
+            TokenType::Error(LexError::FloatOverflow("1e309".to_string())),
+            TokenType::Error(LexError::FloatOverflow("1e999".to_string())),
+            TokenType::Error(LexError::FloatOverflow("2_0e400".to_string())),
+            TokenType::Float(0.0),
+            TokenType::Int(1),
+        ];
+        for expected in expected.iter() {
+            assert_eq!(&lexer.next_token().token_type, expected);
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
     fn test_strings() {
         let input = "\"hello\" \"world\"";
         let mut lexer = Lexer::new(input.to_string());
@@ -213,6 +683,58 @@ mod tests {
         }
     }
     #[test]
+    fn test_string_escapes() {
+        let input = r#""say \"hi\"\n" "tab\there" "back\\slash" "nul\0" "\u{48}\u{e9}\u{1F600}" "cr\r""#;
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            ("say \"hi\"\n", r#""say \"hi\"\n""#),
+            ("tab\there", r#""tab\there""#),
+            ("back\\slash", r#""back\\slash""#),
+            ("nul\0", r#""nul\0""#),
+            ("H\u{e9}\u{1F600}", r#""\u{48}\u{e9}\u{1F600}""#),
+            ("cr\r", r#""cr\r""#),
+        ];
+        for (value, literal) in expected {
+            let token = lexer.next_token();
+            assert_eq!(token.token_type, TokenType::Str(value.to_string()));
+            assert_eq!(token.literal, literal);
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_invalid_escapes() {
+        let cases = [
+            (r#""bad \q escape""#, r"\q"),
+            (r#""\u{110000}""#, r"\u{110000}"),
+            (r#""\u{D800}""#, r"\u{D800}"),
+            (r#""\u{}""#, r"\u{}"),
+            (r#""\u41""#, r"\u"),
+            (r#""\u{41""#, r"\u{41"),
+            (r#""\u{1234567}""#, r"\u{1234567}"),
+        ];
+        for (input, escape) in cases {
+            let mut lexer = Lexer::new(format!("{} x", input));
+            let token = lexer.next_token();
+            assert_eq!(token.token_type, TokenType::Error(LexError::InvalidEscape(escape.to_string())), "input: {}", input);
+            assert_eq!(token.literal, input);
+            assert_eq!(token.span, Span { start: 0, end: input.len(), line: 1, column: 1 });
+            assert_eq!(lexer.next_token().token_type, TokenType::Ident("x".to_string()), "input: {}", input);
+        }
+    }
+    #[test]
+    fn test_unterminated_strings() {
+        for input in ["let s = \"never closed;\nlet t = 1;", "let s = \"ends in escape\\"] {
+            let mut lexer = Lexer::new(input.to_string());
+            lexer.next_token();
+            lexer.next_token();
+            lexer.next_token();
+            let token = lexer.next_token();
+            assert_eq!(token.token_type, TokenType::Error(LexError::UnterminatedString), "input: {}", input);
+            assert_eq!(token.span, Span { start: 8, end: input.len(), line: 1, column: 9 });
+            assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+        }
+    }
+    #[test]
     fn test_whitespace_and_comments() {
         let input = r#"
             // This is a single-line comment
@@ -230,12 +752,118 @@ mod tests {
         assert_eq!(lexer.next_token().token_type, TokenType::EOF);
     }
     #[test]
+    fn test_nested_block_comments() {
+        let input = "a /* outer /* inner */ still comment */ b /*/ not closed yet */ c /**/ d /* ** / */ e";
+        let mut lexer = Lexer::new(input.to_string());
+        for name in ["a", "b", "c", "d", "e"] {
+            assert_eq!(lexer.next_token().token_type, TokenType::Ident(name.to_string()));
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_unterminated_block_comments() {
+        let cases = [
+            ("x /* never closed", Span { start: 2, end: 4, line: 1, column: 3 }),
+            ("x\n  /* outer /* inner */ still open", Span { start: 4, end: 6, line: 2, column: 3 }),
+        ];
+        for (input, span) in cases {
+            let mut lexer = Lexer::new(input.to_string());
+            assert_eq!(lexer.next_token().token_type, TokenType::Ident("x".to_string()));
+            let token = lexer.next_token();
+            assert_eq!(token.token_type, TokenType::Error(LexError::UnterminatedComment), "input: {}", input);
+            assert_eq!(token.span, span, "input: {}", input);
+            assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+        }
+    }
+    #[test]
+    fn test_doc_comments() {
+        let input = "/// Adds two numbers.\n///\n//// not a doc\n// plain\n/** Block doc */ /*** not a doc */ /**/ x";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            (TokenType::DocComment(" Adds two numbers.".to_string()), "/// Adds two numbers."),
+            (TokenType::DocComment("".to_string()), "///"),
+            (TokenType::DocComment(" Block doc ".to_string()), "/** Block doc */"),
+            (TokenType::Ident("x".to_string()), "x"),
+        ];
+        for (token_type, literal) in expected {
+            let token = lexer.next_token();
+            assert_eq!(token.token_type, token_type);
+            assert_eq!(token.literal, literal);
+            assert_eq!(&input[token.span.start..token.span.end], literal);
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_unterminated_doc_comment() {
+        let mut lexer = Lexer::new("/** never closed".to_string());
+        let token = lexer.next_token();
+        assert_eq!(token.token_type, TokenType::Error(LexError::UnterminatedComment));
+        assert_eq!(token.span, Span { start: 0, end: 2, line: 1, column: 1 });
+    }
+    #[test]
+    fn test_lossless_trivia() {
+        let input = "let x = 1; // note\n/* block */\t/// doc\nx\n";
+        let mut lexer = Lexer::new_lossless(input.to_string());
+        let mut rebuilt = String::new();
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            for trivia in &token.leading_trivia {
+                assert_eq!(&input[trivia.span.start..trivia.span.end], trivia.text);
+                rebuilt.push_str(&trivia.text);
+            }
+            rebuilt.push_str(&token.literal);
+            let done = token.token_type == TokenType::EOF;
+            tokens.push(token);
+            if done {
+                break;
+            }
+        }
+        assert_eq!(rebuilt, input);
+
+        let kinds = |token: &Token| token.leading_trivia.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(kinds(&tokens[1]), [TriviaKind::Whitespace]);
+        assert_eq!(tokens[5].token_type, TokenType::DocComment(" doc".to_string()));
+        assert_eq!(kinds(&tokens[5]), [
+            TriviaKind::Whitespace, TriviaKind::LineComment, TriviaKind::Whitespace,
+            TriviaKind::BlockComment, TriviaKind::Whitespace,
+        ]);
+        assert_eq!(tokens[7].token_type, TokenType::EOF);
+        assert_eq!(kinds(&tokens[7]), [TriviaKind::Whitespace]);
+    }
+    #[test]
+    fn test_default_lexer_drops_trivia() {
+        let mut lexer = Lexer::new("  // c\n x".to_string());
+        assert!(lexer.next_token().leading_trivia.is_empty());
+    }
+    #[test]
+    fn test_nul_character_is_not_eof() {
+        let mut lexer = Lexer::new("a\0b".to_string());
+        assert_eq!(lexer.next_token().token_type, TokenType::Ident("a".to_string()));
+        assert_eq!(lexer.next_token().token_type, TokenType::Illegal);
+        assert_eq!(lexer.next_token().token_type, TokenType::Ident("b".to_string()));
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_nul_character_inside_strings() {
+        let types: Vec<TokenType> = Lexer::new("\"a\0b\" x").map(|token| token.token_type).collect();
+        assert_eq!(types, [TokenType::Str("a\0b".to_string()), TokenType::Ident("x".to_string()), TokenType::EOF]);
+        let types: Vec<TokenType> = Lexer::new("\"a\\\0\"").map(|token| token.token_type).collect();
+        assert_eq!(types, [TokenType::Error(LexError::InvalidEscape("\\\0".to_string())), TokenType::EOF]);
+    }
+    #[test]
+    fn test_nul_character_inside_comments() {
+        let types: Vec<TokenType> = Lexer::new("// a\0b\n/* c\0d /* \0 */ */ x").map(|token| token.token_type).collect();
+        assert_eq!(types, [TokenType::Ident("x".to_string()), TokenType::EOF]);
+    }
+    #[test]
     fn test_keywords_and_identifiers() {
-        let input = "let variable = if else fn return";
+        let input = "let variable = if else fn return while break continue";
         let mut lexer = Lexer::new(input.to_string());
         let expected_tokens = [
-            TokenType::Let, TokenType::Ident("variable".to_string()), TokenType::Equal,
+            TokenType::Let, TokenType::Ident("variable".to_string()), TokenType::Assign,
             TokenType::If, TokenType::Else, TokenType::Fn, TokenType::Return,
+            TokenType::While, TokenType::Break, TokenType::Continue,
         ];
         for expected in expected_tokens.iter() {
             let token = lexer.next_token();
@@ -243,6 +871,26 @@ mod tests {
         }
     }
     #[test]
+    fn test_identifiers_with_digits() {
+        let input = "x1 utf8_decode _tmp2 __ a_1_b2 café2 x٣ 9lives";
+        let mut lexer = Lexer::new(input.to_string());
+        for name in ["x1", "utf8_decode", "_tmp2", "__", "a_1_b2", "café2", "x٣"] {
+            assert_eq!(lexer.next_token().token_type, TokenType::Ident(name.to_string()));
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::Error(LexError::InvalidNumber("9lives".to_string())));
+        assert_eq!(lexer.next_token().token_type, TokenType::EOF);
+    }
+    #[test]
+    fn test_keywords_as_identifier_prefixes() {
+        let input = "letter iffy fnord elsewhere returned trueish false_ function2 whileloop breaks continued let if";
+        let mut lexer = Lexer::new(input.to_string());
+        for name in input.split(' ').take(11) {
+            assert_eq!(lexer.next_token().token_type, TokenType::Ident(name.to_string()));
+        }
+        assert_eq!(lexer.next_token().token_type, TokenType::Let);
+        assert_eq!(lexer.next_token().token_type, TokenType::If);
+    }
+    #[test]
     fn test_combined_syntax() {
         let input = "fn add(x, y) { x + y; }";
         let mut lexer = Lexer::new(input.to_string());
@@ -267,6 +915,131 @@ mod tests {
         }
     }
     #[test]
+    fn test_spans() {
+        let input = "let x = 42;\n  foo(\"hi\");";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            (0, 3, 1, 1),
+            (4, 5, 1, 5),
+            (6, 7, 1, 7),
+            (8, 10, 1, 9),
+            (10, 11, 1, 11),
+            (14, 17, 2, 3),
+            (17, 18, 2, 6),
+            (18, 22, 2, 7),
+            (22, 23, 2, 11),
+            (23, 24, 2, 12),
+            (24, 24, 2, 13),
+        ];
+        for (start, end, line, column) in expected {
+            let token = lexer.next_token();
+            assert_eq!(token.span, Span { start, end, line, column }, "span of {:?}", token.token_type);
+        }
+    }
+    #[test]
+    fn test_spans_after_comments() {
+        let input = "// comment\n/* a\nb */ x";
+        let mut lexer = Lexer::new(input.to_string());
+        let token = lexer.next_token();
+        assert_eq!(token.token_type, TokenType::Ident("x".to_string()));
+        assert_eq!((token.span.line, token.span.column), (3, 6));
+    }
+    #[test]
+    fn test_utf8_identifiers_and_strings() {
+        let input = "let café = \"naïve ☃\"; größe";
+        let mut lexer = Lexer::new(input.to_string());
+        let expected = [
+            (TokenType::Let, 0, 3, 1),
+            (TokenType::Ident("café".to_string()), 4, 9, 5),
+            (TokenType::Assign, 10, 11, 10),
+            (TokenType::Str("naïve ☃".to_string()), 12, 24, 12),
+            (TokenType::Semicolon, 24, 25, 21),
+            (TokenType::Ident("größe".to_string()), 26, 33, 23),
+            (TokenType::EOF, 33, 33, 28),
+        ];
+        for (token_type, start, end, column) in expected {
+            let token = lexer.next_token();
+            assert_eq!(token.token_type, token_type);
+            assert_eq!((token.span.start, token.span.end, token.span.column), (start, end, column));
+        }
+    }
+    #[test]
+    fn test_large_input() {
+        let chunk = "let x = 42; // comment\nfn add(a, b) { return a + b; }\n/* block */\n";
+        let input = chunk.repeat(20_000);
+        let mut lexer = Lexer::new(input);
+        let mut count = 0;
+        while lexer.next_token().token_type != TokenType::EOF {
+            count += 1;
+        }
+        assert_eq!(count, 20_000 * 19);
+    }
+    #[test]
+    fn test_iterator_ends_after_eof() {
+        let input = String::from("let x = 1;");
+        let tokens: Vec<TokenType> = Lexer::new(input.as_str()).map(|token| token.token_type).collect();
+        assert_eq!(tokens, [
+            TokenType::Let, TokenType::Ident("x".to_string()), TokenType::Assign,
+            TokenType::Int(1), TokenType::Semicolon, TokenType::EOF,
+        ]);
+        assert_eq!(Lexer::new("").count(), 1);
+    }
+
+    // Hands out at most `step` bytes per read, splitting multi-byte chars
+    // and tokens across reads.
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        step: usize,
+    }
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let count = self.step.min(buf.len()).min(self.bytes.len());
+            buf[..count].copy_from_slice(&self.bytes[..count]);
+            self.bytes = &self.bytes[count..];
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn test_reader_matches_str() {
+        let input = "/// doc\nlet café = \"naïve ☃\\u{1F600}\"; // c\n/* b */ x1 >= 0x1F;";
+        for step in [1, 2, 3, 7, 8192] {
+            let streamed: Vec<Token> = Lexer::from_reader(Trickle { bytes: input.as_bytes(), step }).collect();
+            let in_memory: Vec<Token> = Lexer::new(input).collect();
+            assert_eq!(streamed, in_memory, "step: {}", step);
+        }
+    }
+    #[test]
+    fn test_reader_discards_consumed_input() {
+        let chunk = "let x = 42; // comment\n";
+        let input = chunk.repeat(20_000);
+        let mut lexer = Lexer::from_reader(Trickle { bytes: input.as_bytes(), step: 4096 });
+        let eof = lexer.by_ref().last().expect("EOF token");
+        assert_eq!(eof.token_type, TokenType::EOF);
+        assert_eq!(eof.span.start, input.len());
+        assert_eq!(eof.span.line, 20_001);
+        assert!(lexer.input.len() < 2 * DISCARD_THRESHOLD, "buffer kept {} bytes", lexer.input.len());
+        assert!(lexer.base > 0);
+    }
+    #[test]
+    fn test_reader_invalid_utf8_and_errors() {
+        let bytes = b"a \xFF b";
+        let tokens: Vec<Token> = Lexer::from_reader(&bytes[..]).collect();
+        assert_eq!(tokens[1].token_type, TokenType::Illegal);
+        assert_eq!(tokens[1].literal, "\u{FFFD}");
+        assert_eq!(tokens[2].token_type, TokenType::Ident("b".to_string()));
+
+        struct Failing;
+        impl std::io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+        let tokens: Vec<TokenType> = Lexer::from_reader(Failing).map(|token| token.token_type).collect();
+        assert_eq!(tokens, [TokenType::Error(LexError::Io("disk on fire".to_string())), TokenType::EOF]);
+    }
+    #[test]
     fn test_eof() {
         let input = "variable";
         let mut lexer = Lexer::new(input.to_string());
@@ -0,0 +1,15 @@
+// src/lib.rs
+
+pub mod token;
+pub mod lexer;
+pub mod ast;
+pub mod diagnostic;
+pub mod parser;
+pub mod value;
+pub mod eval;
+pub mod compiler;
+pub mod vm;
+pub mod render;
+pub mod formatter;
+pub mod repl;
+pub mod cst;
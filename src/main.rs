@@ -2,32 +2,184 @@
 
 use std::env;
 use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::process::ExitCode;
+use std::thread;
 
-mod token;
-mod lexer;
-mod ast;
-mod parser;
+use nova_compiler::ast::Program;
+use nova_compiler::diagnostic::Diagnostic;
+use nova_compiler::compiler::Compiler;
+use nova_compiler::eval::STACK_SIZE;
+use nova_compiler::formatter;
+use nova_compiler::lexer::Lexer;
+use nova_compiler::parser::Parser;
+use nova_compiler::render::Renderer;
+use nova_compiler::repl::Repl;
+use nova_compiler::vm::Vm;
 
-use crate::lexer::Lexer;
-use crate::token::TokenType;
+// Every command exits with 0 on success, 1 when the program has errors (or,
+// for `fmt --check`, is not formatted) and 2 when it could not be read or the
+// command line is wrong.
+const SUCCESS: u8 = 0;
+const PROGRAM_ERROR: u8 = 1;
+const USAGE_ERROR: u8 = 2;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: nova_compiler <filename>");
-        std::process::exit(1);
+const USAGE: &str = "\
+Usage: nova_compiler [repl]
+       nova_compiler <command> <file>
+       nova_compiler <file>
+
+Commands:
+  lex            Print the tokens of the file
+  parse          Print the syntax tree of the file
+  check          Report errors in the file without running it
+  run            Run the file (the default when only a file is given)
+  fmt [--check]  Print the file formatted, or with --check only report
+                 whether it already is
+  repl           Start an interactive session (the default with no arguments)
+
+Use '-' as the file to read from standard input.";
+
+#[derive(Clone, Copy)]
+enum Command {
+    Lex,
+    Parse,
+    Check,
+    Run,
+    Format { check: bool },
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (command, path) = match args.as_slice() {
+        [] | ["repl"] => return ExitCode::from(on_big_stack(repl)),
+        ["-h" | "--help"] => {
+            println!("{}", USAGE);
+            return ExitCode::from(SUCCESS);
+        },
+        ["lex", path] => (Command::Lex, *path),
+        ["parse", path] => (Command::Parse, *path),
+        ["check", path] => (Command::Check, *path),
+        ["run", path] => (Command::Run, *path),
+        ["fmt", path] => (Command::Format { check: false }, *path),
+        ["fmt", "--check", path] | ["fmt", path, "--check"] => (Command::Format { check: true }, *path),
+        [path] if !["lex", "parse", "check", "run", "fmt"].contains(path) => (Command::Run, *path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(USAGE_ERROR);
+        },
+    };
+
+    let (file_name, input) = match read_input(path) {
+        Ok(input) => input,
+        Err(error) => {
+            eprintln!("error: could not read {}: {}", path, error);
+            return ExitCode::from(USAGE_ERROR);
+        },
+    };
+    let code = on_big_stack(move || match command {
+        Command::Lex => lex(&file_name, &input),
+        Command::Parse => parse(&file_name, &input).map_or(PROGRAM_ERROR, |program| {
+            println!("{:#?}", program);
+            SUCCESS
+        }),
+        Command::Check => parse(&file_name, &input).map_or(PROGRAM_ERROR, |_| SUCCESS),
+        Command::Run => run(&file_name, &input),
+        Command::Format { check } => format(&file_name, &input, check),
+    });
+    ExitCode::from(code)
+}
+
+// The name to show in diagnostics, and the source.
+fn read_input(path: &str) -> io::Result<(String, String)> {
+    if path == "-" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        return Ok(("<stdin>".to_string(), input));
+    }
+    Ok((path.to_string(), fs::read_to_string(path)?))
+}
+
+// Parsing, printing and formatting recurse on the Rust stack as deep as the
+// program nests, so every command runs on a thread with room to spare.
+fn on_big_stack(task: impl FnOnce() -> u8 + Send + 'static) -> u8 {
+    let runner = thread::Builder::new().stack_size(STACK_SIZE).spawn(task);
+    runner.expect("Could not start worker thread").join().unwrap_or(101)
+}
+
+fn use_color() -> bool {
+    io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+fn report(file_name: &str, input: &str, diagnostics: &[Diagnostic]) {
+    let renderer = Renderer::new(file_name, input).with_color(use_color());
+    for diagnostic in diagnostics {
+        eprintln!("{}", renderer.render(diagnostic));
     }
-    let filename = &args[1];
+}
 
-    let input = fs::read_to_string(filename).expect("Could not read file");
+// Prints every token, reporting the lexical errors among them after.
+fn lex(file_name: &str, input: &str) -> u8 {
+    let mut errors = Vec::new();
+    for token in Lexer::new(input) {
+        println!("{} {:?} {:?}", token.span, token.token_type, token.literal);
+        errors.extend(Diagnostic::from_token(&token));
+    }
+    report(file_name, input, &errors);
+    if errors.is_empty() { SUCCESS } else { PROGRAM_ERROR }
+}
 
-    let mut lexer = Lexer::new(input);
+fn parse(file_name: &str, input: &str) -> Option<Program> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    if parser.errors().is_empty() {
+        return Some(program);
+    }
+    report(file_name, input, parser.errors());
+    eprintln!("{} error(s) found", parser.errors().len());
+    None
+}
+
+fn run(file_name: &str, input: &str) -> u8 {
+    let Some(program) = parse(file_name, input) else {
+        return PROGRAM_ERROR;
+    };
+    match Compiler::new().compile(&program).and_then(|script| Vm::new().run(&script)) {
+        Ok(_) => SUCCESS,
+        Err(error) => {
+            report(file_name, input, &[*error]);
+            PROGRAM_ERROR
+        },
+    }
+}
+
+// Only a program that parses is formatted, so that a typo can't be
+// reflowed into something that means something else.
+fn format(file_name: &str, input: &str, check: bool) -> u8 {
+    if parse(file_name, input).is_none() {
+        return PROGRAM_ERROR;
+    }
+    let formatted = formatter::format(input);
+    if !check {
+        print!("{}", formatted);
+        return SUCCESS;
+    }
+    if formatted == input {
+        SUCCESS
+    } else {
+        eprintln!("{} is not formatted", file_name);
+        PROGRAM_ERROR
+    }
+}
 
-    loop {
-        let token = lexer.next_token();
-        println!("{:?}", token);
-        if token.token_type == TokenType::EOF {
-            break;
-        }
+fn repl() -> u8 {
+    let mut repl = Repl::new(io::stdout(), io::stderr()).with_color(use_color());
+    match repl.run(io::stdin().lock()) {
+        Ok(()) => SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            PROGRAM_ERROR
+        },
     }
-}
\ No newline at end of file
+}
@@ -1,31 +1,113 @@
 // src/parser.rs
 
-use crate::token::{Token, TokenType};
+use crate::token::{Span, Token, TokenType};
 use crate::lexer::Lexer;
-use crate::ast::{Expression, Statement};
+use crate::ast::{Expression, Precedence, Program, Statement};
+use crate::diagnostic::{codes, Diagnostic};
 
-pub struct Parser {
-    lexer: Lexer,
+// Parsing recurses once for every expression or block a program nests, and
+// so does everything that walks the tree after, so nesting is limited to
+// what fits easily on a thread's stack.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
     current_token: Token,
     peek_token: Token,
-    errors: Vec<String>,
+    errors: Vec<Diagnostic>,
+    loop_depth: usize,
+    nesting_depth: usize,
+    // How many `(` of the statement being parsed are still open, so that
+    // recovery knows it is inside them.
+    open_parens: usize,
+    // Where the token before `current_token` ended, for suggesting a `;`.
+    previous_end: Span,
+    // Doc comments read in front of `current_token` and `peek_token`.
+    current_docs: Vec<String>,
+    peek_docs: Vec<String>,
 }
 
-impl Parser {
-    pub fn new(mut lexer: Lexer) -> Self {
-        let current_token = lexer.next_token();
-        let peek_token = lexer.next_token();
+impl<'a> Parser<'a> {
+    pub fn new(mut lexer: Lexer<'a>) -> Self {
+        let mut errors = Vec::new();
+        let mut current_docs = Vec::new();
+        let current_token = Parser::read_token(&mut lexer, &mut current_docs, &mut errors);
+        let mut peek_docs = Vec::new();
+        let peek_token = Parser::read_token(&mut lexer, &mut peek_docs, &mut errors);
         Parser {
             lexer,
             current_token,
             peek_token,
-            errors: Vec::new(),
+            errors,
+            loop_depth: 0,
+            nesting_depth: 0,
+            open_parens: 0,
+            previous_end: Span::default(),
+            current_docs,
+            peek_docs,
         }
     }
 
     fn next_token(&mut self) {
+        match self.current_token.token_type {
+            TokenType::LParen => self.open_parens += 1,
+            TokenType::RParen => self.open_parens = self.open_parens.saturating_sub(1),
+            _ => {},
+        }
+        self.previous_end = self.current_token.end_span();
         std::mem::swap(&mut self.current_token, &mut self.peek_token);
-        self.peek_token = self.lexer.next_token();
+        self.current_docs = std::mem::take(&mut self.peek_docs);
+        self.peek_token = Parser::read_token(&mut self.lexer, &mut self.peek_docs, &mut self.errors);
+    }
+
+    // Doc comments are set aside rather than handed to the grammar, so they
+    // may appear anywhere; only those in front of a declaration are kept.
+    // Lexical errors are reported as soon as their token is read, wherever
+    // it turns up.
+    fn read_token(lexer: &mut Lexer, docs: &mut Vec<String>, errors: &mut Vec<Diagnostic>) -> Token {
+        loop {
+            let token = lexer.next_token();
+            match token.token_type {
+                TokenType::DocComment(text) => docs.push(text),
+                _ => {
+                    errors.extend(Diagnostic::from_token(&token));
+                    return token;
+                },
+            }
+        }
+    }
+
+    // Joins the doc comments in front of the current token, dropping the
+    // single space conventionally written after `///`, and for `/** */`
+    // blocks the leading `*` of each line and the blank opening and closing
+    // lines.
+    fn take_doc(&mut self) -> Option<String> {
+        if self.current_docs.is_empty() {
+            return None;
+        }
+        let mut lines = Vec::new();
+        for text in std::mem::take(&mut self.current_docs) {
+            let mut block: Vec<String> = text
+                .split('\n')
+                .map(|line| {
+                    let trimmed = line.trim_start();
+                    let line = match trimmed.strip_prefix('*') {
+                        Some(rest) if text.contains('\n') => rest,
+                        _ => line,
+                    };
+                    line.strip_prefix(' ').unwrap_or(line).trim_end().to_string()
+                })
+                .collect();
+            if block.len() > 1 {
+                while block.last().is_some_and(|line| line.is_empty()) {
+                    block.pop();
+                }
+                let leading = block.iter().take_while(|line| line.is_empty()).count();
+                block.drain(..leading);
+            }
+            lines.extend(block);
+        }
+        Some(lines.join("\n"))
     }
 
     fn expect_peek(&mut self, t: TokenType) -> bool {
@@ -33,118 +115,484 @@ impl Parser {
             self.next_token();
             true
         } else {
-            self.errors.push(format!(
-                "Expected next token to be {:?}, got {:?} instead",
-                t, self.peek_token.token_type
+            self.error(codes::UNEXPECTED_TOKEN, self.peek_token.span, format!(
+                "Expected {}, found {}", t, self.peek_token.token_type
             ));
             false
         }
     }
 
+    pub fn parse_program(&mut self) -> Program {
+        let mut program = Program::default();
+        while !self.current_token_is(TokenType::EOF) {
+            if let Some(statement) = self.parse_statement_or_recover() {
+                program.statements.push(statement);
+            }
+        }
+        // Lexical errors are found a token ahead of the parser.
+        self.errors.sort_by_key(|error| error.span.start);
+        program
+    }
+
+    // When a statement fails to parse, its error has been reported; skip
+    // the rest of it so that parsing resumes at the next statement instead of
+    // reporting the same mistake again in a different form.
+    fn parse_statement_or_recover(&mut self) -> Option<Statement> {
+        let start = self.current_token.span.start;
+        let outer_parens = std::mem::take(&mut self.open_parens);
+        let statement = self.parse_statement();
+        if statement.is_none() {
+            if self.current_token.span.start == start {
+                self.next_token();
+            }
+            self.synchronize();
+        }
+        self.open_parens = outer_parens;
+        statement
+    }
+
+    // Skips past the next `;`, or up to the next `}` or statement keyword,
+    // ignoring any inside brackets opened along the way, including the `(`s
+    // the statement had open when it failed. A block skipped over ends the
+    // statement too (along with a `;` right after it), unless an `else`
+    // follows or it sat inside parentheses. `fn` and `if` can start an
+    // argument, so only outside of any bracket do they end the statement.
+    fn synchronize(&mut self) {
+        // Whether each bracket still open is a brace, and how many are.
+        let mut open = vec![false; self.open_parens];
+        let mut braces = 0;
+        loop {
+            let in_block = braces > 0;
+            match self.current_token.token_type {
+                TokenType::EOF => return,
+                TokenType::Semicolon if !in_block => {
+                    self.next_token();
+                    return;
+                },
+                TokenType::RBrace if !in_block => return,
+                TokenType::RBrace => {
+                    while open.pop() == Some(false) {}
+                    braces -= 1;
+                    if open.is_empty() {
+                        self.next_token();
+                        if self.current_token_is(TokenType::Semicolon) {
+                            self.next_token();
+                        }
+                        if !self.current_token_is(TokenType::Else) {
+                            return;
+                        }
+                        continue;
+                    }
+                },
+                TokenType::LBrace => {
+                    open.push(true);
+                    braces += 1;
+                },
+                TokenType::LParen => open.push(false),
+                TokenType::RParen if open.last() == Some(&false) => {
+                    open.pop();
+                },
+                TokenType::Let | TokenType::While | TokenType::Return | TokenType::Break | TokenType::Continue
+                    if !in_block => return,
+                TokenType::Fn | TokenType::Function | TokenType::If if open.is_empty() => return,
+                _ => {},
+            }
+            self.next_token();
+        }
+    }
+
     fn parse_statement(&mut self) -> Option<Statement> {
         match self.current_token.token_type {
             TokenType::Let => self.parse_let_statement(),
             TokenType::Return => self.parse_return_statement(),
-            TokenType::Function => self.parse_function_declaration(),
-            _ => {
-                self.errors.push(format!(
-                    "Unhandled statement type: {:?}",
-                    self.current_token.token_type
-                ));
-                None
-            }
+            TokenType::Fn | TokenType::Function if matches!(self.peek_token.token_type, TokenType::Ident(_)) => {
+                self.parse_function_declaration()
+            },
+            TokenType::If => self.parse_if_statement(),
+            TokenType::While => self.parse_while_statement(),
+            TokenType::Break | TokenType::Continue => self.parse_loop_control_statement(),
+            _ => self.parse_expression_statement(),
         }
     }
+    fn error(&mut self, code: &'static str, span: Span, message: String) {
+        self.report(Diagnostic::error(code, span, message));
+    }
+
+    // Only the first problem at a location is kept, so a bad token already
+    // reported by the lexer isn't reported again for not fitting the grammar.
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if !self.errors.iter().any(|error| error.span == diagnostic.span) {
+            self.errors.push(diagnostic);
+        }
+    }
+
+    // Consumes the `;` ending a statement, or reports it missing with a fix
+    // that inserts it right after the previous token.
+    fn expect_semicolon(&mut self, statement: &str) -> bool {
+        if self.current_token_is(TokenType::Semicolon) {
+            self.next_token();
+            return true;
+        }
+        let diagnostic = Diagnostic::error(
+            codes::MISSING_SEMICOLON,
+            self.current_token.span,
+            format!("Expected ';' after {}, found {}", statement, self.current_token.token_type),
+        );
+        self.report(diagnostic.with_suggestion(self.previous_end, ";", "add a semicolon"));
+        false
+    }
+
+    // Reports a missing closing delimiter, pointing back at the opening one.
+    fn unclosed(&mut self, open: Span, expected: &str, context: &str) {
+        let diagnostic = Diagnostic::error(
+            codes::UNCLOSED_DELIMITER,
+            self.current_token.span,
+            format!("Expected '{}' {}, found {}", expected, context, self.current_token.token_type),
+        );
+        self.report(diagnostic.with_label(open, "unclosed delimiter"));
+    }
+
     fn peek_token_is(&self, t: TokenType) -> bool {
         self.peek_token.token_type == t
     }
-    fn parse_expression(&mut self) -> Option<Expression> {
+    fn token_precedence(token_type: &TokenType) -> Precedence {
+        match token_type {
+            TokenType::Assign | TokenType::PlusAssign | TokenType::MinusAssign
+            | TokenType::AsteriskAssign | TokenType::SlashAssign => Precedence::Assign,
+            TokenType::Or => Precedence::LogicalOr,
+            TokenType::And => Precedence::LogicalAnd,
+            TokenType::Eq | TokenType::NotEq => Precedence::Equals,
+            TokenType::LessThan | TokenType::GreaterThan
+            | TokenType::LessEq | TokenType::GreaterEq => Precedence::LessGreater,
+            TokenType::Plus | TokenType::Minus => Precedence::Sum,
+            TokenType::Asterisk | TokenType::Slash => Precedence::Product,
+            TokenType::LParen => Precedence::Call,
+            _ => Precedence::Lowest,
+        }
+    }
+
+    fn current_precedence(&self) -> Precedence {
+        Parser::token_precedence(&self.current_token.token_type)
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+        self.nested(|parser| {
+            let start = parser.current_token.span;
+            let mut left = parser.parse_prefix_expression()?;
+            while precedence < parser.current_precedence() {
+                left = match parser.current_precedence() {
+                    Precedence::Call => parser.parse_call_expression(left, start)?,
+                    Precedence::Assign => parser.parse_assign_expression(left, start)?,
+                    _ => parser.parse_infix_expression(left)?,
+                };
+            }
+            Some(left)
+        })
+    }
+
+    // Runs `parse` one level deeper, unless that is too deep.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        if self.nesting_depth == MAX_NESTING_DEPTH {
+            let diagnostic = Diagnostic::error(codes::NESTING_TOO_DEEP, self.current_token.span, "Nesting too deep");
+            self.report(diagnostic.with_note(format!(
+                "Expressions and blocks may nest at most {} deep", MAX_NESTING_DEPTH
+            )));
+            return None;
+        }
+        self.nesting_depth += 1;
+        let result = parse(self);
+        self.nesting_depth -= 1;
+        result
+    }
+
+    fn parse_prefix_expression(&mut self) -> Option<Expression> {
         let token_type = self.current_token.token_type.clone();
         match token_type {
             TokenType::Ident(name) => {
+                let span = self.current_token.span;
                 self.next_token();
-                Some(Expression::Identifier(name))
+                Some(Expression::Identifier(name, span))
             },
-            TokenType::Int(value) => {
+            TokenType::Int(magnitude) => self.parse_integer_literal(magnitude, false),
+            TokenType::Float(value) => {
                 self.next_token();
-                Some(Expression::IntegerLiteral(value))
+                Some(Expression::FloatLiteral(value))
+            },
+            TokenType::Str(value) => {
+                self.next_token();
+                Some(Expression::StringLiteral(value))
             },
             TokenType::Bool(value) => {
                 self.next_token();
                 Some(Expression::Boolean(value))
             },
+            TokenType::True | TokenType::False => {
+                self.next_token();
+                Some(Expression::Boolean(token_type == TokenType::True))
+            },
+            TokenType::Bang | TokenType::Minus => {
+                let operator = self.current_token.literal.clone();
+                let span = self.current_token.span;
+                self.next_token();
+                // A minus directly in front of a number literal is folded into
+                // it, which is the only way to write `i64::MIN`.
+                if token_type == TokenType::Minus {
+                    match self.current_token.token_type {
+                        TokenType::Int(magnitude) => return self.parse_integer_literal(magnitude, true),
+                        TokenType::Float(value) => {
+                            self.next_token();
+                            return Some(Expression::FloatLiteral(-value));
+                        },
+                        _ => {}
+                    }
+                }
+                let operand = self.parse_expression(Precedence::Prefix)?;
+                Some(Expression::Prefix(operator, Box::new(operand), span))
+            },
+            TokenType::LParen => self.parse_grouped_expression(),
+            TokenType::Fn | TokenType::Function => self.parse_function_literal(),
+            TokenType::If => self.parse_if_expression(),
+            // Already reported when it was read.
+            TokenType::Error(_) | TokenType::Illegal => {
+                self.next_token();
+                None
+            },
             _ => {
-                self.errors.push(format!("Unexpected token in expression: {:?}", self.current_token.token_type));
+                self.error(codes::EXPECTED_EXPRESSION, self.current_token.span, format!(
+                    "Expected an expression, found {}", self.current_token.token_type
+                ));
                 None
             }
         }
     }
-    
-    
+
+    fn parse_integer_literal(&mut self, magnitude: u64, negative: bool) -> Option<Expression> {
+        let value = if negative { -i128::from(magnitude) } else { i128::from(magnitude) };
+        match i64::try_from(value) {
+            Ok(value) => {
+                self.next_token();
+                Some(Expression::IntegerLiteral(value))
+            },
+            Err(_) => {
+                let diagnostic = Diagnostic::error(
+                    codes::INTEGER_OVERFLOW,
+                    self.current_token.span,
+                    format!("Integer literal '{}' is out of range", self.current_token.literal),
+                );
+                self.report(diagnostic.with_note(format!("Integers must be between {} and {}", i64::MIN, i64::MAX)));
+                self.next_token();
+                None
+            }
+        }
+    }
+
+    // All binary operators are left-associative: the right operand is parsed
+    // at the operator's own precedence, so an equal-precedence operator that
+    // follows is folded into the outer loop instead.
+    fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
+        let operator = self.current_token.literal.clone();
+        let span = self.current_token.span;
+        let precedence = self.current_precedence();
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+        Some(Expression::Infix(operator, Box::new(left), Box::new(right), span))
+    }
+
+    // Assignment is right-associative, so `a = b = 1` assigns to `b` first.
+    fn parse_assign_expression(&mut self, target: Expression, target_span: Span) -> Option<Expression> {
+        let operator = self.current_token.literal.clone();
+        let name = match target {
+            Expression::Identifier(name, _) => name,
+            _ => {
+                let diagnostic = Diagnostic::error(
+                    codes::INVALID_ASSIGNMENT_TARGET,
+                    target_span,
+                    format!("Invalid assignment target: {}", target),
+                );
+                self.report(diagnostic
+                    .with_label(self.current_token.span, format!("'{}' needs a variable on its left", operator))
+                    .with_note("Only variables can be assigned to"));
+                return None;
+            }
+        };
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        Some(Expression::Assign(operator, name, Box::new(value), target_span))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<Expression> {
+        let open = self.current_token.span;
+        self.next_token();
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        if !self.current_token_is(TokenType::RParen) {
+            self.unclosed(open, ")", "after expression");
+            return None;
+        }
+        self.next_token();
+        Some(expression)
+    }
+
+    fn parse_call_expression(&mut self, function: Expression, span: Span) -> Option<Expression> {
+        let open = self.current_token.span;
+        self.next_token();
+        let mut arguments = Vec::new();
+        if self.current_token_is(TokenType::RParen) {
+            self.next_token();
+            return Some(Expression::Call(Box::new(function), arguments, span));
+        }
+        loop {
+            arguments.push(self.parse_expression(Precedence::Lowest)?);
+            match self.current_token.token_type {
+                TokenType::Comma => self.next_token(),
+                TokenType::RParen => {
+                    self.next_token();
+                    break;
+                },
+                _ => {
+                    self.unclosed(open, ")", "or ',' in argument list");
+                    return None;
+                }
+            }
+        }
+        Some(Expression::Call(Box::new(function), arguments, span))
+    }
+
     fn parse_let_statement(&mut self) -> Option<Statement> {
+        let doc = self.take_doc();
         self.next_token();
         if let TokenType::Ident(name) = &self.current_token.token_type {
             let variable_name = name.clone();
             self.next_token();
-            if self.current_token.token_type == TokenType::Equal {
+            if self.current_token.token_type == TokenType::Assign {
                 self.next_token();
-                if let Some(expression) = self.parse_expression() {
-                    if self.current_token_is(TokenType::Semicolon) {
-                        self.next_token();
-                        Some(Statement::Let(variable_name, expression))
+                if let Some(expression) = self.parse_expression(Precedence::Lowest) {
+                    if self.expect_semicolon("let statement") {
+                        Some(Statement::Let(variable_name, expression, doc))
                     } else {
-                        self.errors.push("Expected semicolon at end of let statement".to_string());
                         None
                     }
                 } else {
                     None
                 }
             } else {
-                self.errors.push("Expected '=' after variable name".to_string());
+                self.error(codes::UNEXPECTED_TOKEN, self.current_token.span, format!(
+                    "Expected '=' after variable name, found {}", self.current_token.token_type
+                ));
                 None
             }
         } else {
-            self.errors.push("Expected identifier after 'let'".to_string());
+            self.error(codes::EXPECTED_IDENTIFIER, self.current_token.span, format!(
+                "Expected identifier after 'let', found {}", self.current_token.token_type
+            ));
             None
         }
     }
     
+    // The semicolon may be left off the last expression of a block, which
+    // then gives the block its value: `if (a > b) { a } else { b }`.
     fn parse_expression_statement(&mut self) -> Option<Statement> {
-        match &self.current_token.token_type {
-            TokenType::Ident(name) => {
-                let expression = Expression::Identifier(name.clone());
-                self.next_token();
-                if self.current_token_is(TokenType::Semicolon) {
-                    self.next_token();
-                    Some(Statement::Expression(expression))
-                } else {
-                    self.errors.push("Expected semicolon at end of expression statement".to_string());
-                    None
-                }
-            },
-            _ => {
-                self.errors.push(format!("Unexpected token in expression statement: {:?}", self.current_token.token_type));
-                None
-            }
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        if !self.current_token_is(TokenType::RBrace) && !self.expect_semicolon("expression") {
+            return None;
         }
+        Some(Statement::Expression(expression))
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
+    // A statement-level `if` ends at its closing brace; a trailing semicolon
+    // is accepted but not required.
+    fn parse_if_statement(&mut self) -> Option<Statement> {
+        let expression = self.parse_if_expression()?;
+        if self.current_token_is(TokenType::Semicolon) {
+            self.next_token();
+        }
+        Some(Statement::Expression(expression))
+    }
+
+    fn parse_while_statement(&mut self) -> Option<Statement> {
+        if !self.expect_peek(TokenType::LParen) {
+            return None;
+        }
+        let open = self.current_token.span;
         self.next_token();
-        let expr = if let Some(expr) = self.parse_expression() {
-            expr
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        if !self.current_token_is(TokenType::RParen) {
+            self.unclosed(open, ")", "after while condition");
+            return None;
+        }
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+        Some(Statement::While(condition, body?))
+    }
+
+    fn parse_loop_control_statement(&mut self) -> Option<Statement> {
+        let keyword = self.current_token.clone();
+        self.next_token();
+        if !self.expect_semicolon(&format!("'{}'", keyword.literal)) {
+            return None;
+        }
+        if self.loop_depth == 0 {
+            let diagnostic = Diagnostic::error(
+                codes::LOOP_CONTROL_OUTSIDE_LOOP,
+                keyword.span,
+                format!("'{}' outside of a loop", keyword.literal),
+            );
+            self.report(diagnostic.with_note(
+                "'break' and 'continue' only apply to a 'while' loop in the same function"
+            ));
+            return None;
+        }
+        if keyword.token_type == TokenType::Break {
+            Some(Statement::Break)
         } else {
+            Some(Statement::Continue)
+        }
+    }
+
+    fn parse_if_expression(&mut self) -> Option<Expression> {
+        if !self.expect_peek(TokenType::LParen) {
             return None;
-        };
-    
-        if !self.current_token_is(TokenType::Semicolon) {
-            self.errors.push("Expected semicolon at the end of return statement".to_string());
+        }
+        let open = self.current_token.span;
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        if !self.current_token_is(TokenType::RParen) {
+            self.unclosed(open, ")", "after if condition");
+            return None;
+        }
+        if !self.expect_peek(TokenType::LBrace) {
             return None;
         }
+        let consequence = self.parse_block_statement()?;
+
+        let alternative = if self.current_token_is(TokenType::Else) {
+            if self.peek_token_is(TokenType::If) {
+                self.next_token();
+                let nested = self.parse_if_expression()?;
+                Some(vec![Statement::Expression(nested)])
+            } else if self.expect_peek(TokenType::LBrace) {
+                Some(self.parse_block_statement()?)
+            } else {
+                return None;
+            }
+        } else {
+            None
+        };
+        Some(Expression::If(Box::new(condition), consequence, alternative))
+    }
+
+    fn parse_return_statement(&mut self) -> Option<Statement> {
         self.next_token();
+        let expr = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_semicolon("return statement") {
+            return None;
+        }
         Some(Statement::Return(expr))
     }
-    pub fn errors(&self) -> &[String] {
+    pub fn errors(&self) -> &[Diagnostic] {
         &self.errors
     }
 
@@ -152,88 +600,122 @@ impl Parser {
         self.current_token.token_type == t
     }
     fn parse_function_declaration(&mut self) -> Option<Statement> {
-        if !self.expect_peek(TokenType::Function) {
-            return None;
-        }
-        println!("Parsing function declaration. Current token: {:?}", self.current_token);
+        let doc = self.take_doc();
         self.next_token();
         let function_name = if let TokenType::Ident(name) = &self.current_token.token_type {
             name.clone()
         } else {
-            self.errors.push("Expected function name".to_string());
+            self.error(codes::EXPECTED_IDENTIFIER, self.current_token.span, format!(
+                "Expected function name, found {}", self.current_token.token_type
+            ));
             return None;
         };
+        let (parameters, body) = self.parse_function_signature_and_body()?;
+        Some(Statement::Function(function_name, parameters, body, doc))
+    }
 
-        self.next_token();
+    fn parse_function_literal(&mut self) -> Option<Expression> {
+        let (parameters, body) = self.parse_function_signature_and_body()?;
+        Some(Expression::Function(parameters, body))
+    }
+
+    fn parse_function_signature_and_body(&mut self) -> Option<(Vec<String>, Vec<Statement>)> {
         if !self.expect_peek(TokenType::LParen) {
             return None;
         }
-
-        let parameters = self.parse_function_parameters();
-        
+        let parameters = self.parse_function_parameters()?;
         if !self.expect_peek(TokenType::LBrace) {
             return None;
         }
-
+        // A function body starts a new loop context: `break` inside a closure
+        // cannot leave a loop that encloses the closure's definition.
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
         let body = self.parse_block_statement();
-        Some(Statement::Function(function_name, parameters, body))
+        self.loop_depth = loop_depth;
+        Some((parameters, body?))
     }
 
-    fn parse_function_parameters(&mut self) -> Vec<String> {
+    fn parse_function_parameters(&mut self) -> Option<Vec<String>> {
         let mut parameters = Vec::new();
 
         if self.peek_token_is(TokenType::RParen) {
             self.next_token();
-            return parameters;
-        }
-        self.next_token();
-        if let TokenType::Ident(param) = &self.current_token.token_type {
-            parameters.push(param.clone());
+            return Some(parameters);
         }
-        while self.peek_token_is(TokenType::Comma) {
-            self.next_token();
+        loop {
             self.next_token();
             if let TokenType::Ident(param) = &self.current_token.token_type {
                 parameters.push(param.clone());
             } else {
-                self.errors.push("Expected parameter name".to_string());
+                self.error(codes::EXPECTED_IDENTIFIER, self.current_token.span, format!(
+                    "Expected parameter name, found {}", self.current_token.token_type
+                ));
+                return None;
+            }
+            if !self.peek_token_is(TokenType::Comma) {
                 break;
             }
+            self.next_token();
         }
         if !self.expect_peek(TokenType::RParen) {
-            self.errors.push("Expected ')' after parameters".to_string());
+            return None;
         }
-        parameters
+        Some(parameters)
     }
 
-    fn parse_block_statement(&mut self) -> Vec<Statement> {
-        let mut statements = Vec::new();
-        self.next_token();
-        while !self.current_token_is(TokenType::RBrace) && !self.current_token_is(TokenType::EOF) {
-            if let Some(stmt) = self.parse_statement() {
-                statements.push(stmt);
+    fn parse_block_statement(&mut self) -> Option<Vec<Statement>> {
+        self.nested(|parser| {
+            let mut statements = Vec::new();
+            let open = parser.current_token.span;
+            parser.next_token();
+            while !parser.current_token_is(TokenType::RBrace) {
+                if parser.current_token_is(TokenType::EOF) {
+                    parser.unclosed(open, "}", "at end of block");
+                    return None;
+                }
+                if let Some(stmt) = parser.parse_statement_or_recover() {
+                    statements.push(stmt);
+                }
             }
-            self.next_token();
-        }
-        statements
+            parser.next_token();
+            Some(statements)
+        })
     }
 
 }
 
+// Parses a program the test expects to be valid.
+#[cfg(test)]
+pub(crate) fn parse_ok(input: &str) -> Program {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.errors().is_empty(), "Parser errors: {:?}", parser.errors());
+    program
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::lexer::Lexer;
 
+    // The span of the `nth` token spelled `literal` in `input`.
+    fn span_of(input: &str, literal: &str, nth: usize) -> Span {
+        Lexer::new(input).filter(|token| token.literal == literal).nth(nth).expect("token in input").span
+    }
+
+    fn messages(parser: &Parser) -> Vec<String> {
+        parser.errors().iter().map(|error| format!("{}: {}", error.span, error.message)).collect()
+    }
+
     #[test]
     fn test_let_statements() {
         let input = "let x = 5;";
         let lexer = Lexer::new(input.to_string());
         let mut parser = Parser::new(lexer);
         if let Some(statement) = parser.parse_statement() {
-            assert!(matches!(statement, Statement::Let(name, _) if name == "x"));
+            assert!(matches!(statement, Statement::Let(name, _, None) if name == "x"));
         } else {
-            assert!(false, "Failed to parse 'let' statement");
+            panic!("Failed to parse 'let' statement");
         }
         assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
     }
@@ -261,7 +743,10 @@ mod tests {
         assert_eq!(parser.current_token.token_type, TokenType::Ident("example".to_string()));
         if let Some(statement) = parser.parse_expression_statement() {
             match statement {
-                Statement::Expression(Expression::Identifier(name)) => assert_eq!(name, "example"),
+                Statement::Expression(Expression::Identifier(name, span)) => {
+                    assert_eq!(name, "example");
+                    assert_eq!(span, Span { start: 0, end: 7, line: 1, column: 1 });
+                },
                 _ => panic!("Expected Expression statement, found {:?}", statement),
             }
         } else {
@@ -278,6 +763,60 @@ mod tests {
         let lexer = Lexer::new(input.to_string());
         let mut parser = Parser::new(lexer);
         assert_eq!(parser.current_token.token_type, TokenType::Function, "First token is not 'Function'");
+        let statement = parser.parse_statement();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(statement.map(|s| s.to_string()), Some("fn add(x, y) { return (x + y); }".to_string()));
+    }
+    #[test]
+    fn test_fn_declarations() {
+        let input = r#"
+        fn add(a, b) {
+            return a + b;
+        }
+        fn answer() { return 42; }
+        fn outer(x) {
+            fn inner(y) { return x * y; }
+            return inner;
+        }
+        "#;
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(program.statements[0], Statement::Function(
+            "add".to_string(),
+            vec!["a".to_string(), "b".to_string()],
+            vec![Statement::Return(Expression::Infix(
+                "+".to_string(),
+                Box::new(Expression::Identifier("a".to_string(), span_of(input, "a", 1))),
+                Box::new(Expression::Identifier("b".to_string(), span_of(input, "b", 1))),
+                span_of(input, "+", 0),
+            ))],
+            None,
+        ));
+        assert_eq!(program.statements[1].to_string(), "fn answer() { return 42; }");
+        assert_eq!(program.statements[2].to_string(), "fn outer(x) { fn inner(y) { return (x * y); } return inner; }");
+    }
+    #[test]
+    fn test_function_literals() {
+        let input = "let add = fn(a, b) { return a + b; }; let noop = function() {}; fn(x) { return x; }(1);";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert!(matches!(&program.statements[0], Statement::Let(name, Expression::Function(params, body), _)
+            if name == "add" && params.len() == 2 && body.len() == 1));
+        assert_eq!(program.statements[1], Statement::Let("noop".to_string(), Expression::Function(vec![], vec![]), None));
+        assert_eq!(program.statements[2].to_string(), "fn(x) { return x; }(1);");
+    }
+    #[test]
+    fn test_invalid_parameter_lists() {
+        for input in ["fn f(a,) {}", "fn f(1) {}", "fn f(a b) {}", "fn f(a) { return a;"] {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            assert_eq!(parser.parse_statement(), None, "input: {}", input);
+            assert!(!parser.errors.is_empty(), "Expected errors for input: {}", input);
+        }
     }
     
     #[test]
@@ -303,6 +842,522 @@ mod tests {
         }
     }
     #[test]
+    fn test_logical_and_comparison_operators() {
+        let input = "let c = x != y && y <= 10 || z >= 2 == w;";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let statement = parser.parse_statement();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+
+        let ident = |name: &str, nth| Box::new(Expression::Identifier(name.to_string(), span_of(input, name, nth)));
+        let int = |value| Box::new(Expression::IntegerLiteral(value));
+        let infix = |op: &str, left, right| Box::new(Expression::Infix(op.to_string(), left, right, span_of(input, op, 0)));
+        let expected = infix(
+            "||",
+            infix("&&", infix("!=", ident("x", 0), ident("y", 0)), infix("<=", ident("y", 1), int(10))),
+            infix("==", infix(">=", ident("z", 0), int(2)), ident("w", 0)),
+        );
+        assert_eq!(statement, Some(Statement::Let("c".to_string(), *expected, None)));
+    }
+    #[test]
+    fn test_operator_precedence() {
+        let cases = [
+            ("x + 5 - 3 * 7 / 2;", "((x + 5) - ((3 * 7) / 2))"),
+            ("-a * b;", "((-a) * b)"),
+            ("!-a;", "(!(-a))"),
+            ("a + b * c + d / e - f;", "(((a + (b * c)) + (d / e)) - f)"),
+            ("5 > 4 == 3 < 4;", "((5 > 4) == (3 < 4))"),
+            ("-5 - -5;", "(-5 - -5)"),
+            ("-x - -2.5;", "((-x) - -2.5)"),
+            ("-(5);", "(-5)"),
+            ("!true != false;", "((!true) != false)"),
+            ("(a + b) * c;", "((a + b) * c)"),
+            ("-(5 + 5);", "(-(5 + 5))"),
+            ("a - (b - c);", "(a - (b - c))"),
+            ("a + add(b * c) + d;", "((a + add((b * c))) + d)"),
+            ("add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8));", "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))"),
+            ("f()(x);", "f()(x)"),
+        ];
+        for (input, expected) in cases {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            match parser.parse_expression_statement() {
+                Some(Statement::Expression(expression)) => assert_eq!(expression.to_string(), expected, "input: {}", input),
+                other => panic!("Failed to parse {}: {:?}, errors: {:?}", input, other, parser.errors),
+            }
+            assert!(parser.errors.is_empty(), "Parser errors for {}: {:?}", input, parser.errors);
+        }
+    }
+    #[test]
+    fn test_call_expression_tree() {
+        let input = "add(1, -x);";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let expected = Expression::Call(
+            Box::new(Expression::Identifier("add".to_string(), span_of(input, "add", 0))),
+            vec![
+                Expression::IntegerLiteral(1),
+                Expression::Prefix(
+                    "-".to_string(),
+                    Box::new(Expression::Identifier("x".to_string(), span_of(input, "x", 0))),
+                    span_of(input, "-", 0),
+                ),
+            ],
+            span_of(input, "add", 0),
+        );
+        assert_eq!(parser.parse_expression_statement(), Some(Statement::Expression(expected)));
+    }
+    #[test]
+    fn test_unbalanced_parentheses() {
+        for input in ["(a + b;", "add(a, b;", "add(a b);"] {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            assert_eq!(parser.parse_expression_statement(), None, "input: {}", input);
+            assert!(!parser.errors.is_empty(), "Expected errors for input: {}", input);
+        }
+    }
+    #[test]
+    fn test_parse_program() {
+        let input = r#"
+        let x = 42;
+        let y = x + 5 - 3 * 7 / 2;
+        add(x, y);
+        return y;
+        "#;
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "Parser errors: {:?}", parser.errors());
+        assert_eq!(
+            program.to_string(),
+            "let x = 42;\nlet y = ((x + 5) - ((3 * 7) / 2));\nadd(x, y);\nreturn y;\n"
+        );
+    }
+    #[test]
+    fn test_parse_program_collects_every_error() {
+        let input = "let = 1;\nlet a = 2;\nlet b 3;\nlet c = 4;";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(program.statements.contains(&Statement::Let("a".to_string(), Expression::IntegerLiteral(2), None)));
+        assert!(program.statements.contains(&Statement::Let("c".to_string(), Expression::IntegerLiteral(4), None)));
+        assert_eq!(messages(&parser), [
+            "1:5: Expected identifier after 'let', found '='",
+            "3:7: Expected '=' after variable name, found number '3'",
+        ]);
+    }
+    #[test]
+    fn test_if_statements() {
+        let input = r#"
+        if (x > y) {
+            let result = x - y;
+            print(result);
+        } else {
+            print(y);
+        }
+        if (x) { x; }
+        "#;
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(program.statements.len(), 2);
+        let ident = |name: &str, nth| Box::new(Expression::Identifier(name.to_string(), span_of(input, name, nth)));
+        assert_eq!(program.statements[0], Statement::Expression(Expression::If(
+            Box::new(Expression::Infix(">".to_string(), ident("x", 0), ident("y", 0), span_of(input, ">", 0))),
+            vec![
+                Statement::Let(
+                    "result".to_string(),
+                    Expression::Infix("-".to_string(), ident("x", 1), ident("y", 1), span_of(input, "-", 0)),
+                    None,
+                ),
+                Statement::Expression(Expression::Call(
+                    ident("print", 0),
+                    vec![*ident("result", 1)],
+                    span_of(input, "print", 0),
+                )),
+            ],
+            Some(vec![Statement::Expression(Expression::Call(
+                ident("print", 1),
+                vec![*ident("y", 2)],
+                span_of(input, "print", 1),
+            ))]),
+        )));
+        assert_eq!(program.statements[1].to_string(), "if (x) { x; };");
+    }
+    #[test]
+    fn test_else_if_chains() {
+        let input = "if (a < 0) { -1 } else if (a == 0) { 0 } else { 1 }";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(
+            program.to_string(),
+            "if ((a < 0)) { -1; } else { if ((a == 0)) { 0; } else { 1; }; };\n"
+        );
+    }
+    #[test]
+    fn test_if_expressions() {
+        let input = "let m = if (a > b) { a } else { b }; let n = 1 + if (c) { 2 } else { 3 } * 4;";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(
+            program.to_string(),
+            "let m = if ((a > b)) { a; } else { b; };\nlet n = (1 + (if (c) { 2; } else { 3; } * 4));\n"
+        );
+    }
+    #[test]
+    fn test_malformed_if() {
+        for input in ["if x > y { x }", "if (x > y { x }", "if (x) x;", "if (x) { x } else y;"] {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            assert_eq!(parser.parse_statement(), None, "input: {}", input);
+            assert!(!parser.errors.is_empty(), "Expected errors for input: {}", input);
+        }
+    }
+    #[test]
+    fn test_while_loops() {
+        let input = r#"
+        let i = 0;
+        while (i < 10) {
+            if (i == 5) { break; }
+            if (i == 2) { continue; }
+            print(i);
+        }
+        "#;
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(
+            program.statements[1].to_string(),
+            "while ((i < 10)) { if ((i == 5)) { break; }; if ((i == 2)) { continue; }; print(i); }"
+        );
+    }
+    #[test]
+    fn test_break_outside_loop() {
+        let inputs = [
+            ("break;", "1:1: 'break' outside of a loop"),
+            ("if (x) { continue; }", "1:10: 'continue' outside of a loop"),
+            ("while (x) { fn f() { break; } }", "1:22: 'break' outside of a loop"),
+        ];
+        for (input, expected) in inputs {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            parser.parse_program();
+            assert_eq!(messages(&parser), [expected], "input: {}", input);
+            assert_eq!(parser.errors()[0].code, codes::LOOP_CONTROL_OUTSIDE_LOOP);
+        }
+    }
+    #[test]
+    fn test_assignments() {
+        let cases = [
+            ("i = i + 1;", "(i = (i + 1));"),
+            ("total += x * 2;", "(total += (x * 2));"),
+            ("n -= 1;", "(n -= 1);"),
+            ("n *= 2;", "(n *= 2);"),
+            ("n /= 2;", "(n /= 2);"),
+            ("a = b = c;", "(a = (b = c));"),
+            ("x = y == z;", "(x = (y == z));"),
+        ];
+        for (input, expected) in cases {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let statement = parser.parse_statement();
+            assert!(parser.errors.is_empty(), "Parser errors for {}: {:?}", input, parser.errors);
+            assert_eq!(statement.map(|s| s.to_string()), Some(expected.to_string()), "input: {}", input);
+        }
+    }
+    #[test]
+    fn test_invalid_assignment_targets() {
+        let cases = [
+            ("1 = 2;", "1:1: Invalid assignment target: 1"),
+            ("x + y = 3;", "1:1: Invalid assignment target: (x + y)"),
+            ("let a = 1;\n  f() += 3;", "2:3: Invalid assignment target: f()"),
+            ("a = -b = 1;", "1:5: Invalid assignment target: (-b)"),
+        ];
+        for (input, expected) in cases {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            parser.parse_program();
+            assert_eq!(messages(&parser).first().map(String::as_str), Some(expected), "input: {}", input);
+            assert_eq!(parser.errors()[0].code, codes::INVALID_ASSIGNMENT_TARGET);
+        }
+    }
+    #[test]
+    fn test_number_literals() {
+        let input = "let area = 3.14 * r * r; let mask = 0xFF - 0b1010; let big = 1_000_000 * 1e-9;";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(program.to_string(), "let area = ((3.14 * r) * r);\nlet mask = (255 - 10);\nlet big = (1000000 * 1e-9);\n");
+    }
+    #[test]
+    fn test_integer_boundaries() {
+        let cases = [
+            ("9223372036854775807;", Some(Expression::IntegerLiteral(i64::MAX))),
+            ("-9223372036854775808;", Some(Expression::IntegerLiteral(i64::MIN))),
+            ("-9223372036854775807;", Some(Expression::IntegerLiteral(-i64::MAX))),
+            ("-0;", Some(Expression::IntegerLiteral(0))),
+        ];
+        for (input, expected) in cases {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            assert_eq!(parser.parse_statement(), expected.map(Statement::Expression), "input: {}", input);
+            assert!(parser.errors.is_empty(), "Parser errors for {}: {:?}", input, parser.errors);
+        }
+    }
+    #[test]
+    fn test_integer_overflow_is_reported() {
+        let cases = [
+            ("let a = 9223372036854775808;", "1:9: Integer literal '9223372036854775808' is out of range"),
+            ("let b = -9223372036854775809;", "1:10: Integer literal '9223372036854775809' is out of range"),
+            ("let c = 99999999999999999999;", "1:9: Integer literal '99999999999999999999' is out of range"),
+            ("let d = -(9223372036854775808);", "1:11: Integer literal '9223372036854775808' is out of range"),
+        ];
+        for (input, expected) in cases {
+            let lexer = Lexer::new(format!("{} let ok = 1;", input));
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            assert_eq!(messages(&parser), [expected], "input: {}", input);
+            assert_eq!(parser.errors()[0].code, codes::INTEGER_OVERFLOW);
+            assert_eq!(program.statements, [Statement::Let("ok".to_string(), Expression::IntegerLiteral(1), None)]);
+        }
+    }
+    #[test]
+    fn test_string_literals() {
+        let input = r#"print("Sum is:", sum); let greeting = "hello, " + name + "!";"#;
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(program.statements[0], Statement::Expression(Expression::Call(
+            Box::new(Expression::Identifier("print".to_string(), span_of(input, "print", 0))),
+            vec![
+                Expression::StringLiteral("Sum is:".to_string()),
+                Expression::Identifier("sum".to_string(), span_of(input, "sum", 0)),
+            ],
+            span_of(input, "print", 0),
+        )));
+        assert_eq!(program.statements[1].to_string(), r#"let greeting = (("hello, " + name) + "!");"#);
+    }
+    #[test]
+    fn test_lexical_errors_are_reported() {
+        let input = "let s = \"bad \\q\";";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        parser.parse_program();
+        assert_eq!(messages(&parser), ["1:9: Invalid escape sequence '\\q' in string literal"]);
+        assert_eq!(parser.errors()[0].code, codes::INVALID_ESCAPE);
+    }
+    #[test]
+    fn test_example_program() {
+        let lexer = Lexer::new(include_str!("../example.nova").to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert_eq!(program.statements.len(), 10);
+    }
+    #[test]
+    fn test_doc_comments_attach_to_declarations() {
+        let input = r#"
+        /// Adds two numbers.
+        ///
+        /// Both must be integers.
+        fn add(a, b) { return a + b; }
+
+        /**
+         * The answer.
+         */
+        let answer = 42;
+
+        //// Not a doc comment.
+        let plain = 1;
+
+        /// Dangling: not followed by a declaration.
+        print(/// ignored
+            plain);
+        let after = 2;
+        "#;
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "Parser errors: {:?}", parser.errors);
+        assert!(matches!(&program.statements[0], Statement::Function(name, _, _, Some(doc))
+            if name == "add" && doc == "Adds two numbers.\n\nBoth must be integers."));
+        assert!(matches!(&program.statements[1], Statement::Let(name, _, Some(doc))
+            if name == "answer" && doc == "The answer."));
+        assert!(matches!(&program.statements[2], Statement::Let(_, _, None)));
+        assert!(matches!(&program.statements[4], Statement::Let(name, _, None) if name == "after"));
+        assert_eq!(
+            program.statements[1].to_string(),
+            "/// The answer.\nlet answer = 42;"
+        );
+    }
+    #[test]
+    fn test_recovery_reports_each_mistake_once() {
+        let input = r#"
+        let x = 42;
+        let y = (x + ;
+        fn add(a, b) {
+            let sum = a +* b;
+            return sum;
+        }
+        let z = 5
+        let w = add(x, z);
+        print(w);
+        "#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert_eq!(messages(&parser), [
+            "3:22: Expected an expression, found ';'",
+            "5:26: Expected an expression, found '*'",
+            "9:9: Expected ';' after let statement, found 'let'",
+        ]);
+        assert_eq!(
+            program.to_string(),
+            "let x = 42;\nfn add(a, b) { return sum; }\nlet w = add(x, z);\nprint(w);\n"
+        );
+    }
+    #[test]
+    fn test_recovery_inside_nested_blocks() {
+        let input = r#"
+        while (i < 10) {
+            if (i == ) { break; }
+            i += 1;
+        }
+        } let after = 1;
+        let broken = fn(a) { a + };
+        let last = 2;
+        "#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert_eq!(messages(&parser), [
+            "3:22: Expected an expression, found ')'",
+            "6:9: Expected an expression, found '}'",
+            "7:34: Expected an expression, found '}'",
+        ]);
+        assert_eq!(
+            program.to_string(),
+            "while ((i < 10)) { (i += 1); }\nlet after = 1;\nlet broken = fn(a) {  };\nlet last = 2;\n"
+        );
+    }
+    #[test]
+    fn test_recovery_inside_parentheses() {
+        let input = r#"
+        print(a, {1});
+        let a = (1 + {2});
+        print(f(1, {2}), if (x) { 1 } else { 2 });
+        print(1,
+        let b = 2;
+        let c = fn() { g(1, {3}); 4 };
+        let d = 5;
+        "#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert_eq!(messages(&parser), [
+            "2:18: Expected an expression, found '{'",
+            "3:22: Expected an expression, found '{'",
+            "4:20: Expected an expression, found '{'",
+            "6:9: Expected an expression, found 'let'",
+            "7:29: Expected an expression, found '{'",
+        ]);
+        assert_eq!(program.to_string(), "let b = 2;\nlet c = fn() { 4; };\nlet d = 5;\n");
+    }
+    #[test]
+    fn test_nesting_is_limited() {
+        let nested = |depth: usize| format!("let x = {}1{};\nlet y = 2;", "(".repeat(depth), ")".repeat(depth));
+        // The `let` value is the first level.
+        parse_ok(&nested(MAX_NESTING_DEPTH - 1));
+        let too_deep = nested(100_000);
+        let mut parser = Parser::new(Lexer::new(&too_deep));
+        let program = parser.parse_program();
+        assert_eq!(messages(&parser), [format!("1:{}: Nesting too deep", 9 + MAX_NESTING_DEPTH)]);
+        assert_eq!(parser.errors()[0].code, codes::NESTING_TOO_DEEP);
+        assert_eq!(program.to_string(), "let y = 2;\n");
+
+        let blocks = format!("{}1{}", "if (x) { ".repeat(1000), " }".repeat(1000));
+        let mut parser = Parser::new(Lexer::new(&blocks));
+        parser.parse_program();
+        assert_eq!(parser.errors().len(), 1);
+    }
+    #[test]
+    fn test_recovery_skips_else_branches() {
+        let input = "if (a == ) { b; } else { c; }\nlet d = fn() { e; };\nlet f = 1;";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert_eq!(messages(&parser), ["1:10: Expected an expression, found ')'"]);
+        assert_eq!(program.to_string(), "let d = fn() { e; };\nlet f = 1;\n");
+    }
+    #[test]
+    fn test_recovery_from_unclosed_block() {
+        let lexer = Lexer::new("let a = 1;\nfn f() {\n  let b = 2;\n");
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert_eq!(messages(&parser), ["4:1: Expected '}' at end of block, found end of file"]);
+        assert_eq!(parser.errors()[0].code, codes::UNCLOSED_DELIMITER);
+        assert_eq!(parser.errors()[0].labels[0].span.to_string(), "2:8");
+        assert_eq!(program.to_string(), "let a = 1;\n");
+    }
+    #[test]
+    fn test_error_locations() {
+        let input = "let x = 5;\nlet y = 10\nlet z = 15;";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        parser.parse_statement();
+        parser.parse_statement();
+        assert_eq!(messages(&parser), ["3:1: Expected ';' after let statement, found 'let'"]);
+        let suggestion = &parser.errors()[0].suggestions[0];
+        assert_eq!(suggestion.replacement, ";");
+        assert_eq!((suggestion.span.start, suggestion.span.line, suggestion.span.column), (21, 2, 11));
+    }
+    #[test]
+    fn test_diagnostics_name_tokens_readably() {
+        let cases = [
+            ("while x { }", codes::UNEXPECTED_TOKEN, "1:7: Expected '(', found identifier 'x'"),
+            ("(a + b;", codes::UNCLOSED_DELIMITER, "1:7: Expected ')' after expression, found ';'"),
+            ("add(a b);", codes::UNCLOSED_DELIMITER, "1:7: Expected ')' or ',' in argument list, found identifier 'b'"),
+            ("fn f(1) {}", codes::EXPECTED_IDENTIFIER, "1:6: Expected parameter name, found number '1'"),
+            ("return 1", codes::MISSING_SEMICOLON, "1:9: Expected ';' after return statement, found end of file"),
+            ("let a = @;", codes::ILLEGAL_CHARACTER, "1:9: Unexpected character '@'"),
+        ];
+        for (input, code, expected) in cases {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(messages(&parser).first().map(String::as_str), Some(expected), "input: {}", input);
+            assert_eq!(parser.errors()[0].code, code, "input: {}", input);
+        }
+    }
+    #[test]
+    fn test_lexical_errors_are_reported_wherever_they_occur() {
+        let cases = [
+            ("let x = 1 /* open", [codes::UNTERMINATED_COMMENT]),
+            ("print(1, 2 \"unterminated", [codes::UNTERMINATED_STRING]),
+            ("fn f(a, 0x) {}", [codes::INVALID_NUMBER]),
+            ("let a = @;", [codes::ILLEGAL_CHARACTER]),
+        ];
+        for (input, expected) in cases {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            let codes: Vec<&str> = parser.errors().iter().map(|error| error.code).collect();
+            assert_eq!(codes, expected, "input: {}", input);
+        }
+        let mut parser = Parser::new(Lexer::new("let a = 1 b; let c = @;"));
+        parser.parse_program();
+        assert_eq!(messages(&parser), [
+            "1:11: Expected ';' after let statement, found identifier 'b'",
+            "1:22: Unexpected character '@'",
+        ]);
+    }
+    #[test]
     fn test_unexpected_token() {
         let input = r#"
         let x = function(y, z) return y + z; // Using 'function' in an expression improperly
@@ -0,0 +1,228 @@
+// src/render.rs
+
+use std::fmt::Write;
+
+use crate::diagnostic::{Diagnostic, Severity, Suggestion};
+use crate::token::Span;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const GREEN: &str = "\x1b[1;32m";
+const BLUE: &str = "\x1b[1;34m";
+
+// Renders diagnostics the way rustc does: a header with the code and
+// message, the location, then each source line involved with the primary
+// span underlined by `^` and labels by `-`, followed by notes and fixes.
+pub struct Renderer<'a> {
+    file_name: &'a str,
+    source: &'a str,
+    color: bool,
+}
+
+struct Annotation<'a> {
+    span: Span,
+    primary: bool,
+    message: &'a str,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file_name: &'a str, source: &'a str) -> Self {
+        Renderer { file_name, source, color: false }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut annotations = vec![Annotation { span: diagnostic.span, primary: true, message: "" }];
+        annotations.extend(diagnostic.labels.iter().map(|label| Annotation {
+            span: label.span,
+            primary: false,
+            message: &label.message,
+        }));
+        annotations.sort_by_key(|annotation| (annotation.span.line, !annotation.primary));
+
+        let last_line = annotations.iter().map(|annotation| annotation.span.line)
+            .chain(diagnostic.suggestions.iter().map(|suggestion| suggestion.span.line))
+            .max()
+            .unwrap_or(1);
+        let width = last_line.to_string().len();
+        let pad = " ".repeat(width);
+
+        let mut out = String::new();
+        let severity = format!("{}[{}]", diagnostic.severity, diagnostic.code);
+        let style = match diagnostic.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => GREEN,
+        };
+        let _ = writeln!(out, "{}{}", self.paint(style, &severity), self.paint(BOLD, &format!(": {}", diagnostic.message)));
+        let _ = writeln!(out, "{}{} {}:{}", pad, self.paint(BLUE, "-->"), self.file_name, diagnostic.span);
+        let _ = writeln!(out, "{} {}", pad, self.paint(BLUE, "|"));
+
+        let mut previous_line = None;
+        for annotation in &annotations {
+            let (line_start, text) = self.line_at(annotation.span.start);
+            if previous_line != Some(annotation.span.line) {
+                if previous_line.is_some_and(|previous| annotation.span.line > previous + 1) {
+                    let _ = writeln!(out, "{}", self.paint(BLUE, "..."));
+                }
+                self.write_source_line(&mut out, width, annotation.span.line, text);
+                previous_line = Some(annotation.span.line);
+            }
+            let (offset, length) = Renderer::underline(
+                text, annotation.span.start - line_start, annotation.span.end - line_start
+            );
+            let (marker, style) = if annotation.primary { ('^', style) } else { ('-', BLUE) };
+            let mut underline: String = std::iter::repeat_n(marker, length).collect();
+            if !annotation.message.is_empty() {
+                underline = format!("{} {}", underline, annotation.message);
+            }
+            let _ = writeln!(out, "{} {} {}{}", pad, self.paint(BLUE, "|"), offset, self.paint(style, &underline));
+        }
+
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{} {} {}: {}", pad, self.paint(BLUE, "="), self.paint(BOLD, "note"), note);
+        }
+        for suggestion in &diagnostic.suggestions {
+            self.write_suggestion(&mut out, width, suggestion);
+        }
+        out
+    }
+
+    // Shows the line the suggestion applies to with the fix made, marking
+    // the new text with `+`.
+    fn write_suggestion(&self, out: &mut String, width: usize, suggestion: &Suggestion) {
+        let pad = " ".repeat(width);
+        let (line_start, text) = self.line_at(suggestion.span.start);
+        let start = suggestion.span.start - line_start;
+        let end = (suggestion.span.end - line_start).min(text.len());
+        let fixed = format!("{}{}{}", &text[..start], suggestion.replacement, &text[end..]);
+        let (offset, _) = Renderer::underline(&fixed, start, 0);
+        let added: String = std::iter::repeat_n('+', suggestion.replacement.chars().count()).collect();
+
+        let _ = writeln!(out, "{}{}", self.paint(GREEN, "help"), self.paint(BOLD, &format!(": {}", suggestion.message)));
+        let _ = writeln!(out, "{} {}", pad, self.paint(BLUE, "|"));
+        self.write_source_line(out, width, suggestion.span.line, &fixed);
+        let _ = writeln!(out, "{} {} {}{}", pad, self.paint(BLUE, "|"), offset, self.paint(GREEN, &added));
+    }
+
+    fn write_source_line(&self, out: &mut String, width: usize, line: usize, text: &str) {
+        let number = format!("{:>width$} |", line, width = width);
+        if text.is_empty() {
+            let _ = writeln!(out, "{}", self.paint(BLUE, &number));
+        } else {
+            let _ = writeln!(out, "{} {}", self.paint(BLUE, &number), text);
+        }
+    }
+
+    // The byte offset where the line holding `offset` starts, and its text
+    // without the line ending.
+    fn line_at(&self, offset: usize) -> (usize, &'a str) {
+        let offset = offset.min(self.source.len());
+        let start = self.source[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+        let end = self.source[start..].find('\n').map_or(self.source.len(), |newline| start + newline);
+        (start, self.source[start..end].trim_end_matches('\r'))
+    }
+
+    // The whitespace that lines a marker up under column `start` of `text`
+    // (keeping tabs, so it lines up however wide they display), and how many
+    // characters of the line the marker covers: at least one, and never past
+    // the end of the line. `end` is relative to the start of the line.
+    fn underline(text: &str, start: usize, end: usize) -> (String, usize) {
+        let start = start.min(text.len());
+        let offset = text[..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let end = end.clamp(start, text.len());
+        (offset, text[start..end].chars().count().max(1))
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn render_all(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_program();
+        let renderer = Renderer::new("test.nova", source);
+        parser.errors().iter().map(|error| renderer.render(error)).collect()
+    }
+
+    #[test]
+    fn test_render_primary_span_and_note() {
+        let rendered = render_all("let x = 1;\nlet y = 99999999999999999999 + x;\n");
+        assert_eq!(rendered, [
+"error[E0005]: Integer literal '99999999999999999999' is out of range
+ --> test.nova:2:9
+  |
+2 | let y = 99999999999999999999 + x;
+  |         ^^^^^^^^^^^^^^^^^^^^
+  = note: Integers must be between -9223372036854775808 and 9223372036854775807
+"]);
+    }
+
+    #[test]
+    fn test_render_label_and_gap() {
+        let source = "fn f() {\n  let a = 1;\n  let b = 2;\n";
+        let rendered = render_all(source);
+        assert_eq!(rendered, [
+"error[E0105]: Expected '}' at end of block, found end of file
+ --> test.nova:4:1
+  |
+1 | fn f() {
+  |        - unclosed delimiter
+...
+4 |
+  | ^
+"]);
+    }
+
+    #[test]
+    fn test_render_suggestion() {
+        let rendered = render_all("let y = 10\nlet z = 15;");
+        assert_eq!(rendered, [
+"error[E0103]: Expected ';' after let statement, found 'let'
+ --> test.nova:2:1
+  |
+2 | let z = 15;
+  | ^^^
+help: add a semicolon
+  |
+1 | let y = 10;
+  |           +
+"]);
+    }
+
+    #[test]
+    fn test_render_tabs_and_unicode() {
+        let rendered = render_all("\tlet café = @;");
+        assert!(rendered[0].contains("1 | \tlet café = @;\n  | \t           ^\n"), "{}", rendered[0]);
+    }
+
+    #[test]
+    fn test_render_with_color() {
+        let source = "break;";
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_program();
+        let plain = Renderer::new("test.nova", source).render(&parser.errors()[0]);
+        let colored = Renderer::new("test.nova", source).with_color(true).render(&parser.errors()[0]);
+        assert!(!plain.contains('\x1b'));
+        assert!(colored.starts_with("\x1b[1;31merror[E0107]\x1b[0m"));
+        let stripped = colored.replace(RESET, "").replace(BOLD, "").replace(RED, "").replace(BLUE, "");
+        assert_eq!(stripped, plain);
+    }
+}
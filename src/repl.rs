@@ -0,0 +1,193 @@
+// src/repl.rs
+
+use std::io::{self, BufRead, Write};
+
+use crate::ast::Program;
+use crate::diagnostic::{codes, Diagnostic};
+use crate::compiler::Compiler;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::render::Renderer;
+use crate::token::{LexError, TokenType};
+use crate::value::Value;
+use crate::vm::Vm;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION: &str = ".. ";
+const FILE_NAME: &str = "<repl>";
+
+// Reads entries one at a time and runs each on the same VM, compiled by the
+// same compiler, so bindings carry over from one to the next. Values and
+// prompts go to the VM's output, next to anything the program prints;
+// diagnostics go to `errors`.
+pub struct Repl<W: Write, E: Write> {
+    compiler: Compiler,
+    vm: Vm<W>,
+    errors: E,
+    color: bool,
+}
+
+impl<W: Write, E: Write> Repl<W, E> {
+    pub fn new(output: W, errors: E) -> Self {
+        Repl { compiler: Compiler::new(), vm: Vm::with_output(output), errors, color: false }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn output(&self) -> &W {
+        self.vm.output()
+    }
+
+    pub fn errors(&self) -> &E {
+        &self.errors
+    }
+
+    // Runs until the input ends. Only failing to read or write stops it
+    // early; errors in the entries themselves are reported and skipped.
+    pub fn run(&mut self, input: impl BufRead) -> io::Result<()> {
+        let mut lines = input.lines();
+        while let Some(entry) = self.read_entry(&mut lines)? {
+            if !entry.trim().is_empty() {
+                self.eval(&entry)?;
+            }
+        }
+        writeln!(self.vm.output_mut())
+    }
+
+    // An entry runs on until its brackets are balanced and its strings and
+    // comments are closed. `None` means the input ended before one started.
+    fn read_entry(&mut self, lines: &mut impl Iterator<Item = io::Result<String>>) -> io::Result<Option<String>> {
+        let mut entry = String::new();
+        self.prompt(PROMPT)?;
+        for line in lines {
+            entry.push_str(&line?);
+            entry.push('\n');
+            if !is_incomplete(&entry) {
+                return Ok(Some(entry));
+            }
+            self.prompt(CONTINUATION)?;
+        }
+        Ok((!entry.is_empty()).then_some(entry))
+    }
+
+    fn prompt(&mut self, prompt: &str) -> io::Result<()> {
+        let output = self.vm.output_mut();
+        write!(output, "{}", prompt)?;
+        output.flush()
+    }
+
+    fn eval(&mut self, source: &str) -> io::Result<()> {
+        let program = match parse_entry(source) {
+            Ok(program) => program,
+            Err(errors) => return self.report(source, &errors),
+        };
+        match self.compiler.compile(&program).and_then(|script| self.vm.run(&script)) {
+            Ok(Value::Null) => Ok(()),
+            Ok(value) => writeln!(self.vm.output_mut(), "{}", value),
+            Err(error) => self.report(source, &[*error]),
+        }
+    }
+
+    fn report(&mut self, source: &str, diagnostics: &[Diagnostic]) -> io::Result<()> {
+        let renderer = Renderer::new(FILE_NAME, source).with_color(self.color);
+        for diagnostic in diagnostics {
+            write!(self.errors, "{}", renderer.render(diagnostic))?;
+        }
+        self.errors.flush()
+    }
+}
+
+fn parse(source: &str) -> (Program, Vec<Diagnostic>) {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program();
+    (program, parser.errors().to_vec())
+}
+
+// Like a file, except that the entry's last expression may leave off its
+// semicolon, as in `1 + 2`.
+fn parse_entry(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    let (program, errors) = parse(source);
+    if errors.is_empty() {
+        return Ok(program);
+    }
+    let trimmed = source.trim_end();
+    if let [error] = errors.as_slice() {
+        if error.code == codes::MISSING_SEMICOLON && error.span.start >= trimmed.len() {
+            let (program, retry_errors) = parse(&format!("{};", trimmed));
+            if retry_errors.is_empty() {
+                return Ok(program);
+            }
+        }
+    }
+    Err(errors)
+}
+
+// Unbalanced closing brackets count as complete, so the parser can report
+// them rather than the REPL waiting forever.
+fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    for token in Lexer::new(source) {
+        match token.token_type {
+            TokenType::LParen | TokenType::LBrace => depth += 1,
+            TokenType::RParen | TokenType::RBrace => depth -= 1,
+            TokenType::Error(LexError::UnterminatedString | LexError::UnterminatedComment) => return true,
+            _ => {},
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds `input` to a fresh REPL, returning its output and errors.
+    fn session(input: &str) -> (String, String) {
+        let mut repl = Repl::new(Vec::new(), Vec::new());
+        repl.run(input.as_bytes()).unwrap();
+        (String::from_utf8(repl.output().clone()).unwrap(), String::from_utf8(repl.errors().clone()).unwrap())
+    }
+
+    #[test]
+    fn test_bindings_persist_between_entries() {
+        let (output, errors) = session("let x = 40;\nfn add(n) { x + n }\nadd(2);\nx = 1;\nadd(2)\n");
+        assert_eq!(output, ">> >> >> 42\n>> 1\n>> 3\n>> \n");
+        assert_eq!(errors, "");
+    }
+
+    #[test]
+    fn test_multi_line_entries() {
+        let (output, errors) = session("fn f(a,\n  b) {\n  a * b\n}\nf(6, 7);\nlet s = \"two\nlines\"; s\n");
+        assert_eq!(output, ">> .. .. .. >> 42\n>> .. two\nlines\n>> \n");
+        assert_eq!(errors, "");
+    }
+
+    #[test]
+    fn test_errors_are_reported_without_exiting() {
+        let (output, errors) = session("let x = ;\n1 / 0;\nprint(\"still here\");\n)\n");
+        assert_eq!(output, ">> >> >> still here\n>> >> \n");
+        assert!(errors.starts_with("error[E0101]: Expected an expression, found ';'\n --> <repl>:1:9\n"), "{}", errors);
+        assert!(errors.contains("error[E0203]: Division by zero\n --> <repl>:1:3\n"), "{}", errors);
+        assert!(errors.contains("error[E0101]: Expected an expression, found ')'"), "{}", errors);
+    }
+
+    #[test]
+    fn test_input_ending_mid_entry_still_runs() {
+        let (output, errors) = session("print(1,\n2)");
+        assert_eq!(output, ">> .. 1 2\n>> \n");
+        assert_eq!(errors, "");
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(is_incomplete("fn f() {"));
+        assert!(is_incomplete("print((1 + 2)"));
+        assert!(is_incomplete("\"open"));
+        assert!(is_incomplete("/* open"));
+        assert!(!is_incomplete("print(\"{\"); // {"));
+        assert!(!is_incomplete("}"));
+    }
+}
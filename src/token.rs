@@ -1,18 +1,32 @@
 // src/token.rs
 
+use std::fmt;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     EOF,
     Ident(String),
-    Int(i64),
+    // The magnitude of an integer literal; the sign comes from a unary minus,
+    // which lets `-9223372036854775808` be written.
+    Int(u64),
+    Float(f64),
     Plus,
     Minus,
     Asterisk,
     Slash,
-    Equal,
-    NotEqual,
+    Assign,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    Eq,
+    NotEq,
     LessThan,
     GreaterThan,
+    LessEq,
+    GreaterEq,
+    And,
+    Or,
     Illegal,
     Let,
     If,
@@ -30,11 +44,145 @@ pub enum TokenType {
     Str(String),
     Bool(bool),
     Function,
-    Bang
+    Bang,
+    While,
+    Break,
+    Continue,
+    // The text of a `///` or `/** */` comment, without its delimiters.
+    DocComment(String),
+    Error(LexError),
+}
+
+// How a token is named in diagnostics: `expected ';', found end of file`.
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            TokenType::EOF => return write!(f, "end of file"),
+            TokenType::Ident(name) => return write!(f, "identifier '{}'", name),
+            TokenType::Int(value) => return write!(f, "number '{}'", value),
+            TokenType::Float(value) => return write!(f, "number '{}'", value),
+            TokenType::Str(_) => return write!(f, "string literal"),
+            TokenType::Bool(value) => return write!(f, "'{}'", value),
+            TokenType::DocComment(_) => return write!(f, "doc comment"),
+            TokenType::Illegal => return write!(f, "illegal character"),
+            TokenType::Error(_) => return write!(f, "invalid token"),
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Asterisk => "*",
+            TokenType::Slash => "/",
+            TokenType::Assign => "=",
+            TokenType::PlusAssign => "+=",
+            TokenType::MinusAssign => "-=",
+            TokenType::AsteriskAssign => "*=",
+            TokenType::SlashAssign => "/=",
+            TokenType::Eq => "==",
+            TokenType::NotEq => "!=",
+            TokenType::LessThan => "<",
+            TokenType::GreaterThan => ">",
+            TokenType::LessEq => "<=",
+            TokenType::GreaterEq => ">=",
+            TokenType::And => "&&",
+            TokenType::Or => "||",
+            TokenType::Let => "let",
+            TokenType::If => "if",
+            TokenType::Else => "else",
+            TokenType::Fn => "fn",
+            TokenType::Return => "return",
+            TokenType::True => "true",
+            TokenType::False => "false",
+            TokenType::LParen => "(",
+            TokenType::RParen => ")",
+            TokenType::LBrace => "{",
+            TokenType::RBrace => "}",
+            TokenType::Comma => ",",
+            TokenType::Semicolon => ";",
+            TokenType::Function => "function",
+            TokenType::Bang => "!",
+            TokenType::While => "while",
+            TokenType::Break => "break",
+            TokenType::Continue => "continue",
+        };
+        write!(f, "'{}'", symbol)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexError {
+    UnterminatedString,
+    InvalidEscape(String),
+    InvalidNumber(String),
+    IntegerOverflow(String),
+    FloatOverflow(String),
+    UnterminatedComment,
+    Io(String),
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::UnterminatedString => write!(f, "Unterminated string literal"),
+            LexError::InvalidEscape(escape) => write!(f, "Invalid escape sequence '{}' in string literal", escape),
+            LexError::InvalidNumber(literal) => write!(f, "Invalid number literal '{}'", literal),
+            LexError::IntegerOverflow(literal) => write!(f, "Integer literal '{}' is out of range", literal),
+            LexError::FloatOverflow(literal) => write!(f, "Float literal '{}' is out of range", literal),
+            LexError::UnterminatedComment => write!(f, "Unterminated block comment"),
+            LexError::Io(message) => write!(f, "Failed to read source: {}", message),
+        }
+    }
+}
+
+// Byte offsets are half-open (`start..end`); line and column are 1-based and
+// point at the first character of the token.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+// `literal` is always the exact source text of the token. `leading_trivia`
+// is only filled in by a lossless lexer (see `Lexer::new_lossless`); the
+// trivia at the end of the input belongs to the EOF token.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
-}
\ No newline at end of file
+    pub span: Span,
+    pub leading_trivia: Vec<Trivia>,
+}
+
+impl Token {
+    // The empty span just past the end of the token, where a missing `;`
+    // would be inserted.
+    pub fn end_span(&self) -> Span {
+        let (line, column) = match self.literal.rfind('\n') {
+            Some(newline) => (
+                self.span.line + self.literal.matches('\n').count(),
+                self.literal[newline + 1..].chars().count() + 1,
+            ),
+            None => (self.span.line, self.span.column + self.literal.chars().count()),
+        };
+        Span { start: self.span.end, end: self.span.end, line, column }
+    }
+}
@@ -0,0 +1,243 @@
+// src/value.rs
+
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::ast::Statement;
+use crate::diagnostic::{codes, Diagnostic};
+use crate::eval::Function;
+use crate::token::Span;
+use crate::vm::Closure;
+
+// Both the interpreter and the VM stop a program whose calls nest deeper
+// than this.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+// Values, and the operations on them, shared by the interpreter and the VM
+// so that both ways of running a program agree on what every operation does
+// and how it fails.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(Rc<str>),
+    // A function as the interpreter runs it, and as the VM does.
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Builtin(Builtin),
+    Null,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Builtin {
+    Print,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 1] = [Builtin::Print];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Print => "print",
+        }
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::Closure(_) | Value::Builtin(_) => "function",
+            Value::Null => "null",
+        }
+    }
+
+    // Only `false` and `null` are false in a condition.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Boolean(false) | Value::Null)
+    }
+}
+
+// Integers and floats compare by value; functions are only equal to
+// themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Integer(a), Value::Float(b)) | (Value::Float(b), Value::Integer(a)) => *a as f64 == *b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            (Value::Builtin(a), Value::Builtin(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+// How `print` shows a value: strings without quotes.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Function(function) => write_function_name(f, &function.name),
+            Value::Closure(closure) => write_function_name(f, &closure.function.name),
+            Value::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name()),
+            Value::Null => write!(f, "null"),
+        }
+    }
+}
+
+fn write_function_name(f: &mut fmt::Formatter, name: &Option<String>) -> fmt::Result {
+    match name {
+        Some(name) => write!(f, "<fn {}>", name),
+        None => write!(f, "<fn>"),
+    }
+}
+
+
+pub(crate) fn call_builtin(
+    output: &mut impl Write,
+    builtin: Builtin,
+    arguments: &[Value],
+    span: Span,
+) -> Result<Value, Box<Diagnostic>> {
+    match builtin {
+        Builtin::Print => {
+            let line: Vec<String> = arguments.iter().map(Value::to_string).collect();
+            writeln!(output, "{}", line.join(" ")).map_err(|error| {
+                Box::new(Diagnostic::error(codes::OUTPUT_ERROR, span, format!("Failed to write output: {}", error)))
+            })?;
+            Ok(Value::Null)
+        },
+    }
+}
+
+pub(crate) fn undefined_variable(name: &str, span: Span) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(codes::UNDEFINED_VARIABLE, span, format!("Undefined variable '{}'", name)))
+}
+
+pub(crate) fn undefined_assignment(name: &str, span: Span) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(codes::UNDEFINED_VARIABLE, span, format!("Cannot assign to undefined variable '{}'", name))
+        .with_note("Declare it first with 'let'"))
+}
+
+pub(crate) fn not_callable(value: &Value, span: Span) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(codes::NOT_CALLABLE, span, format!("A value of type {} is not a function", value.type_name())))
+}
+
+pub(crate) fn stack_overflow(span: Span) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(codes::STACK_OVERFLOW, span, "Maximum call depth exceeded")
+        .with_note(format!("Calls may nest at most {} deep", MAX_CALL_DEPTH)))
+}
+
+// The parser rejects these, but a program can be built without it.
+pub(crate) fn loop_control_outside_loop(statement: &Statement) -> Box<Diagnostic> {
+    let keyword = if matches!(statement, Statement::Break) { "break" } else { "continue" };
+    Box::new(Diagnostic::error(codes::LOOP_CONTROL_OUTSIDE_LOOP, Span::default(), format!("'{}' outside of a loop", keyword)))
+}
+
+pub(crate) fn wrong_argument_count(name: &Option<String>, expected: usize, given: usize, span: Span) -> Box<Diagnostic> {
+    let name = name.as_deref().unwrap_or("anonymous function");
+    Box::new(Diagnostic::error(
+        codes::WRONG_ARGUMENT_COUNT,
+        span,
+        format!("'{}' expects {} argument{}, got {}", name, expected, if expected == 1 { "" } else { "s" }, given),
+    ))
+}
+
+fn type_mismatch(message: String, span: Span) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(codes::TYPE_MISMATCH, span, message))
+}
+
+pub(crate) fn unary(operator: &str, operand: Value, span: Span) -> Result<Value, Box<Diagnostic>> {
+    match (operator, operand) {
+        ("!", operand) => Ok(Value::Boolean(!operand.is_truthy())),
+        ("-", Value::Integer(value)) => value.checked_neg().map(Value::Integer).ok_or_else(|| {
+            Box::new(Diagnostic::error(codes::ARITHMETIC_OVERFLOW, span, format!("Integer overflow negating {}", value)))
+        }),
+        ("-", Value::Float(value)) => Ok(Value::Float(-value)),
+        (operator, operand) => Err(type_mismatch(
+            format!("Cannot apply '{}' to a value of type {}", operator, operand.type_name()),
+            span,
+        )),
+    }
+}
+
+// Arithmetic on two integers stays in integers and fails on overflow;
+// mixing in a float makes it floating-point. `+` also joins strings.
+pub(crate) fn binary(operator: &str, left: Value, right: Value, span: Span) -> Result<Value, Box<Diagnostic>> {
+    match (&left, &right) {
+        (Value::Integer(a), Value::Integer(b)) => integer_binary(operator, *a, *b, span),
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            Ok(float_binary(operator, as_float(&left), as_float(&right)))
+        },
+        (Value::String(a), Value::String(b)) if operator == "+" => Ok(Value::String(Rc::from(format!("{}{}", a, b)))),
+        (Value::String(a), Value::String(b)) if matches!(operator, "<" | ">" | "<=" | ">=") => {
+            Ok(Value::Boolean(compare(operator, a, b)))
+        },
+        _ if operator == "==" => Ok(Value::Boolean(left == right)),
+        _ if operator == "!=" => Ok(Value::Boolean(left != right)),
+        _ => Err(type_mismatch(
+            format!("Cannot apply '{}' to values of type {} and {}", operator, left.type_name(), right.type_name()),
+            span,
+        )),
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Integer(value) => *value as f64,
+        Value::Float(value) => *value,
+        _ => unreachable!("only called on numbers"),
+    }
+}
+
+fn compare<T: PartialOrd + ?Sized>(operator: &str, a: &T, b: &T) -> bool {
+    match operator {
+        "<" => a < b,
+        ">" => a > b,
+        "<=" => a <= b,
+        ">=" => a >= b,
+        "==" => a == b,
+        _ => a != b,
+    }
+}
+
+fn integer_binary(operator: &str, a: i64, b: i64, span: Span) -> Result<Value, Box<Diagnostic>> {
+    let result = match operator {
+        "+" => a.checked_add(b),
+        "-" => a.checked_sub(b),
+        "*" => a.checked_mul(b),
+        "/" if b == 0 => return Err(Box::new(Diagnostic::error(codes::DIVISION_BY_ZERO, span, "Division by zero"))),
+        "/" => a.checked_div(b),
+        _ => return Ok(Value::Boolean(compare(operator, &a, &b))),
+    };
+    result.map(Value::Integer).ok_or_else(|| {
+        Box::new(Diagnostic::error(
+            codes::ARITHMETIC_OVERFLOW,
+            span,
+            format!("Integer overflow computing {} {} {}", a, operator, b),
+        ))
+    })
+}
+
+fn float_binary(operator: &str, a: f64, b: f64) -> Value {
+    match operator {
+        "+" => Value::Float(a + b),
+        "-" => Value::Float(a - b),
+        "*" => Value::Float(a * b),
+        "/" => Value::Float(a / b),
+        _ => Value::Boolean(compare(operator, &a, &b)),
+    }
+}
@@ -0,0 +1,477 @@
+// src/vm.rs
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::compiler::{Access, CompiledFunction, Instruction, Script, UpvalueSource};
+use crate::diagnostic::Diagnostic;
+use crate::token::Span;
+use crate::value::{self, Builtin, Value, MAX_CALL_DEPTH};
+
+// A variable captured by a closure. It points into the stack while the
+// function that declared it is still running, and holds the value itself
+// once that slot goes away.
+#[derive(Debug)]
+enum Upvalue {
+    Open(usize),
+    Closed(Option<Value>),
+}
+
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+// The upvalues are left out: a recursive closure captures itself.
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("name", &self.function.name)
+            .field("arity", &self.function.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base: usize,
+}
+
+// Runs compiled scripts on a value stack. Nova calls don't recurse on the
+// Rust stack here, so only `MAX_CALL_DEPTH` limits recursion. A local's slot
+// is `None` until its declaration runs, as is a global's.
+pub struct Vm<W: Write = io::Stdout> {
+    stack: Vec<Option<Value>>,
+    frames: Vec<Frame>,
+    globals: Vec<Option<Value>>,
+    global_names: Vec<String>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    output: W,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm::with_output(io::stdout())
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl<W: Write> Vm<W> {
+    pub fn with_output(output: W) -> Self {
+        let globals = Builtin::ALL.iter().map(|&builtin| Some(Value::Builtin(builtin))).collect();
+        Vm {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            global_names: Vec::new(),
+            open_upvalues: Vec::new(),
+            output,
+        }
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    // Runs a script from the same `Compiler` as any run before, so its
+    // globals are still there, and gives back the value it finished with.
+    pub fn run(&mut self, script: &Script) -> Result<Value, Box<Diagnostic>> {
+        self.globals.resize(script.globals.len(), None);
+        self.global_names.clone_from(&script.globals);
+        let closure = Rc::new(Closure { function: Rc::clone(&script.function), upvalues: Vec::new() });
+        self.push(Value::Closure(Rc::clone(&closure)));
+        self.frames.push(Frame { closure, ip: 0, base: 0 });
+        let result = self.execute();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<Value, Box<Diagnostic>> {
+        loop {
+            let frame = self.frames.last_mut().expect("a running frame");
+            let chunk = &frame.closure.function.chunk;
+            let instruction = chunk.code[frame.ip];
+            let span = chunk.spans[frame.ip];
+            let base = frame.base;
+            frame.ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    let value = chunk.constants[index as usize].clone();
+                    self.push(value);
+                },
+                Instruction::Null => self.push(Value::Null),
+                Instruction::True => self.push(Value::Boolean(true)),
+                Instruction::False => self.push(Value::Boolean(false)),
+                Instruction::Pop => {
+                    self.pop();
+                },
+                Instruction::Swap => {
+                    let length = self.stack.len();
+                    self.stack.swap(length - 1, length - 2);
+                },
+                Instruction::GetGlobal(slot) => match &self.globals[slot as usize] {
+                    Some(value) => self.push(value.clone()),
+                    None => return Err(value::undefined_variable(&self.global_names[slot as usize], span)),
+                },
+                Instruction::GetGlobalForUpdate(slot) => match &self.globals[slot as usize] {
+                    Some(value) => self.push(value.clone()),
+                    None => return Err(value::undefined_assignment(&self.global_names[slot as usize], span)),
+                },
+                Instruction::DefineGlobal(slot) => {
+                    let value = self.pop();
+                    self.globals[slot as usize] = Some(value);
+                },
+                Instruction::SetGlobal(slot) => {
+                    let value = self.peek().clone();
+                    match &mut self.globals[slot as usize] {
+                        Some(global) => *global = value,
+                        None => return Err(value::undefined_assignment(&self.global_names[slot as usize], span)),
+                    }
+                },
+                Instruction::GetLocal(slot) => self.stack.push(self.stack[base + slot as usize].clone()),
+                Instruction::SetLocal(slot) => self.stack[base + slot as usize] = Some(self.peek().clone()),
+                Instruction::GetUpvalue(index) => {
+                    let value = match &*self.upvalue(index).borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                },
+                Instruction::SetUpvalue(index) => {
+                    let value = self.peek().clone();
+                    self.write(Access::Upvalue(index), base, value);
+                },
+                Instruction::Uninitialized => self.stack.push(None),
+                Instruction::GetVariable(index) | Instruction::GetVariableForUpdate(index) => {
+                    let function = Rc::clone(&frame.closure.function);
+                    let lookup = &function.chunk.lookups[index as usize];
+                    match lookup.candidates.iter().find_map(|&access| self.read(access, base)) {
+                        Some(value) => self.push(value),
+                        None if matches!(instruction, Instruction::GetVariable(_)) => {
+                            return Err(value::undefined_variable(&lookup.name, span));
+                        },
+                        None => return Err(value::undefined_assignment(&lookup.name, span)),
+                    }
+                },
+                Instruction::SetVariable(index) => {
+                    let function = Rc::clone(&frame.closure.function);
+                    let lookup = &function.chunk.lookups[index as usize];
+                    let value = self.peek().clone();
+                    let Some(&access) = lookup.candidates.iter().find(|&&access| self.read(access, base).is_some()) else {
+                        return Err(value::undefined_assignment(&lookup.name, span));
+                    };
+                    self.write(access, base, value);
+                },
+                Instruction::Negate => {
+                    let operand = self.pop();
+                    self.push(value::unary("-", operand, span)?);
+                },
+                Instruction::Not => {
+                    let operand = self.pop();
+                    self.push(value::unary("!", operand, span)?);
+                },
+                Instruction::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.push(value::binary(operator.symbol(), left, right, span)?);
+                },
+                Instruction::Jump(target) => self.frame().ip = target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    if !self.pop().is_truthy() {
+                        self.frame().ip = target as usize;
+                    }
+                },
+                Instruction::Call(arguments) => self.call(arguments as usize, span)?,
+                Instruction::Closure(index) => {
+                    let function = Rc::clone(&chunk.functions[index as usize]);
+                    let upvalues = function.upvalues.iter().map(|&source| match source {
+                        UpvalueSource::Local(slot) => self.capture(base + slot as usize),
+                        UpvalueSource::Upvalue(index) => Rc::clone(self.upvalue(index)),
+                    }).collect();
+                    self.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                },
+                Instruction::EndScope(count) => {
+                    let value = self.pop();
+                    let length = self.stack.len() - count as usize;
+                    self.close_upvalues(length);
+                    self.stack.truncate(length);
+                    self.push(value);
+                },
+                Instruction::Truncate(height) => {
+                    self.close_upvalues(base + height as usize);
+                    self.stack.truncate(base + height as usize);
+                },
+                Instruction::Return => {
+                    let value = self.pop();
+                    self.close_upvalues(base);
+                    self.stack.truncate(base);
+                    self.frames.pop();
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    self.push(value);
+                },
+            }
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a running frame")
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(Some(value));
+    }
+
+    // Only locals are ever unbound, and the compiler reads those through a
+    // lookup unless they are certain to be bound.
+    fn pop(&mut self) -> Value {
+        self.stack.pop().flatten().expect("compiled code keeps the stack balanced")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().and_then(Option::as_ref).expect("compiled code keeps the stack balanced")
+    }
+
+    // The value of a variable, or `None` if it isn't bound yet.
+    fn read(&self, access: Access, base: usize) -> Option<Value> {
+        match access {
+            Access::Local(slot) => self.stack[base + slot as usize].clone(),
+            Access::Upvalue(index) => match &*self.upvalue(index).borrow() {
+                Upvalue::Open(slot) => self.stack[*slot].clone(),
+                Upvalue::Closed(value) => value.clone(),
+            },
+            Access::Global(slot) => self.globals[slot as usize].clone(),
+        }
+    }
+
+    fn write(&mut self, access: Access, base: usize, value: Value) {
+        match access {
+            Access::Local(slot) => self.stack[base + slot as usize] = Some(value),
+            Access::Upvalue(index) => {
+                let upvalue = Rc::clone(self.upvalue(index));
+                let mut upvalue = upvalue.borrow_mut();
+                match &mut *upvalue {
+                    Upvalue::Open(slot) => self.stack[*slot] = Some(value),
+                    Upvalue::Closed(closed) => *closed = Some(value),
+                }
+            },
+            Access::Global(slot) => self.globals[slot as usize] = Some(value),
+        }
+    }
+
+    fn upvalue(&self, index: u32) -> &Rc<RefCell<Upvalue>> {
+        &self.frames.last().expect("a running frame").closure.upvalues[index as usize]
+    }
+
+    // The callee sits under its arguments, which become the first locals of
+    // its frame.
+    fn call(&mut self, arguments: usize, span: Span) -> Result<(), Box<Diagnostic>> {
+        let callee = self.stack.len() - arguments - 1;
+        let closure = match &self.stack[callee] {
+            Some(Value::Closure(closure)) => Rc::clone(closure),
+            Some(Value::Builtin(builtin)) => {
+                let builtin = *builtin;
+                let arguments: Vec<Value> = self.stack.drain(callee + 1..).flatten().collect();
+                let result = value::call_builtin(&mut self.output, builtin, &arguments, span)?;
+                self.stack.truncate(callee);
+                self.push(result);
+                return Ok(());
+            },
+            other => return Err(value::not_callable(other.as_ref().expect("callees are values"), span)),
+        };
+        if arguments != closure.function.arity {
+            return Err(value::wrong_argument_count(&closure.function.name, closure.function.arity, arguments, span));
+        }
+        if self.frames.len() - 1 == MAX_CALL_DEPTH {
+            return Err(value::stack_overflow(span));
+        }
+        self.frames.push(Frame { closure, ip: 0, base: callee });
+        Ok(())
+    }
+
+    // Shares one upvalue between every closure capturing the same slot, so
+    // they all see each other's assignments.
+    fn capture(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self.open_upvalues.iter().find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // Moves the values of slots about to be dropped into the upvalues that
+    // captured them.
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                },
+                _ => true,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::eval::{self, Interpreter};
+    use crate::parser::parse_ok;
+
+    const SAMPLES: [(&str, &str); 9] = [
+        ("example.nova", include_str!("../example.nova")),
+        ("closures.nova", include_str!("../samples/closures.nova")),
+        ("recursion.nova", include_str!("../samples/recursion.nova")),
+        ("loops.nova", include_str!("../samples/loops.nova")),
+        ("values.nova", include_str!("../samples/values.nova")),
+        ("scopes.nova", include_str!("../samples/scopes.nova")),
+        ("errors.nova", include_str!("../samples/errors.nova")),
+        ("type_error.nova", include_str!("../samples/type_error.nova")),
+        ("arity_error.nova", include_str!("../samples/arity_error.nova")),
+    ];
+
+    fn run(vm: &mut Vm<Vec<u8>>, compiler: &mut Compiler, input: &str) -> Result<Value, String> {
+        compiler.compile(&parse_ok(input)).and_then(|script| vm.run(&script)).map_err(|error| error.to_string())
+    }
+
+    fn value(input: &str) -> Value {
+        run(&mut Vm::with_output(Vec::new()), &mut Compiler::new(), input).unwrap_or_else(|error| panic!("{}: {}", input, error))
+    }
+
+    #[test]
+    fn test_values() {
+        let cases = [
+            ("1 + 2 * 3;", Value::Integer(7)),
+            ("let x = 2; x *= 5; x;", Value::Integer(10)),
+            ("if (false) { 1 }", Value::Null),
+            ("fn add(a, b) { a + b } add(2, 3);", Value::Integer(5)),
+            ("false || 0;", Value::Boolean(true)),
+            ("let n = 0; while (n < 5) { n += 1; } n;", Value::Integer(5)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(value(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_closures_share_captured_variables() {
+        let input = "
+            fn counter() {
+                let count = 0;
+                let inc = fn() { count += 1 };
+                let get = fn() { count };
+                return fn(bump) { if (bump) { inc() } else { get() } };
+            }
+            let c = counter();
+            c(true); c(true);
+            c(false);
+        ";
+        assert_eq!(value(input), Value::Integer(2));
+    }
+
+    #[test]
+    fn test_deep_recursion_needs_no_big_stack() {
+        let input = "fn down(n) { if (n == 0) { 0 } else { down(n - 1) } } down(999);";
+        assert_eq!(value(input), Value::Integer(0));
+        let input = "fn down(n) { if (n == 0) { 0 } else { down(n - 1) } } down(1000);";
+        let error = run(&mut Vm::with_output(Vec::new()), &mut Compiler::new(), input).unwrap_err();
+        assert_eq!(error, "1:39: error[E0207]: Maximum call depth exceeded");
+    }
+
+    #[test]
+    fn test_globals_persist_across_runs() {
+        let mut vm = Vm::with_output(Vec::new());
+        let mut compiler = Compiler::new();
+        assert_eq!(run(&mut vm, &mut compiler, "let x = 40; fn add(n) { x + n }"), Ok(Value::Null));
+        assert_eq!(run(&mut vm, &mut compiler, "undefined;").unwrap_err(), "1:1: error[E0201]: Undefined variable 'undefined'");
+        assert_eq!(run(&mut vm, &mut compiler, "add(2);"), Ok(Value::Integer(42)));
+    }
+
+    // What a program printed and the value or error it finished with, run
+    // both ways.
+    fn run_both(input: &str) -> [(String, Result<String, String>); 2] {
+        let program = parse_ok(input);
+        let mut interpreter = Interpreter::with_output(Vec::new());
+        let interpreted = interpreter.run(&program).map(|value| value.to_string()).map_err(|error| error.to_string());
+        let mut vm = Vm::with_output(Vec::new());
+        let compiled = Compiler::new().compile(&program).and_then(|script| vm.run(&script)).map(|value| value.to_string()).map_err(|error| error.to_string());
+        [
+            (String::from_utf8(interpreter.output().clone()).unwrap(), interpreted),
+            (String::from_utf8(vm.output().clone()).unwrap(), compiled),
+        ]
+    }
+
+    // The interpreter recurses on the Rust stack, so this runs on a thread
+    // with room for it, as `main` does.
+    fn assert_same_behavior(cases: Vec<(&'static str, String)>) {
+        let runner = std::thread::Builder::new().stack_size(eval::STACK_SIZE).spawn(move || {
+            for (name, input) in cases {
+                let [interpreted, compiled] = run_both(&input);
+                assert_eq!(compiled, interpreted, "{} behaves differently in the VM", name);
+            }
+        });
+        runner.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_samples_match_interpreter() {
+        assert_same_behavior(SAMPLES.iter().map(|&(name, input)| (name, input.to_string())).collect());
+    }
+
+    #[test]
+    fn test_edge_cases_match_interpreter() {
+        let cases = [
+            "let x = 1; x;",
+            "let a = 1; a += a += 2; a;",
+            "undefined += 1;",
+            "undefined = 1;",
+            "let n = 5; n(1);",
+            "-\"s\";",
+            "9223372036854775807 + 1;",
+            "fn f(a) { a } f(1, 2);",
+            "print(print);",
+            "return 7; print(1);",
+            "let i = 0; while (i < 3) { i += 1; if (i == 2) { return i * 100; } }",
+            "fn f() { let k = 0; while (true) { let g = fn() { k }; k += 1; if (k == 3) { return g; } } } f()();",
+            "fn f(x) { if (x) { let y = 1; return fn() { y }; } 0 } f(true)();",
+            "fn f() { let a = 1; let b = 2; let g = fn() { a = a + b; a }; g(); g() } f();",
+            "let f = fn() { f }; f() == f;",
+            "fn f() { fn even(n) { if (n == 0) { true } else { odd(n - 1) } } fn odd(n) { if (n == 0) { false } else { even(n - 1) } } even(10) } f();",
+            "let x = 1; fn f() { let g = fn() { x }; let x = 5; g() } f();",
+            "fn f() { let g = fn() { x }; let x = 5; g() } f();",
+            "fn f() { let g = if (true) { fn() { g } } else { 0 }; g() == g } f();",
+            "fn f() { g(); fn g() { 1 } } f();",
+            "let x = 1; fn f() { let a = x; let x = 2; a + x } f();",
+            "let x = 1; fn f() { let g = fn() { x += 10 }; g(); let x = 2; g(); x } f() + x;",
+            "fn f() { let g = fn() { y = 3 }; g() } f();",
+            "let i = 0; let fs = 0; while (i < 2) { let h = fn() { v }; if (i == 1) { fs = h; } let v = i; i += 1; } fs();",
+            "fn down(n) { if (n == 0) { 0 } else { down(n - 1) } } down(1000);",
+        ];
+        assert_same_behavior(cases.iter().map(|&input| (input, input.to_string())).collect());
+    }
+}